@@ -10,10 +10,35 @@ fn main() {
 		.build("src/lib.rs");
 
 	println!(r"cargo:rustc-link-search=C:\Users\tokgeo\source\repos\Detours\lib.X64");
+	println!(r"cargo:rerun-if-changed=src/actions.rs");
+	println!(r"cargo:rerun-if-changed=src/approval.rs");
+	println!(r"cargo:rerun-if-changed=src/args.rs");
+	println!(r"cargo:rerun-if-changed=src/audit.rs");
+	println!(r"cargo:rerun-if-changed=src/automation.rs");
+	println!(r"cargo:rerun-if-changed=src/control.rs");
 	println!(r"cargo:rerun-if-changed=src/detour.rs");
+	println!(r"cargo:rerun-if-changed=src/environment.rs");
+	println!(r"cargo:rerun-if-changed=src/events.rs");
 	println!(r"cargo:rerun-if-changed=src/export.rs");
+	println!(r"cargo:rerun-if-changed=src/helix.rs");
+	println!(r"cargo:rerun-if-changed=src/hooks.rs");
 	println!(r"cargo:rerun-if-changed=src/http.rs");
+	println!(r"cargo:rerun-if-changed=src/image.rs");
+	println!(r"cargo:rerun-if-changed=src/killswitch.rs");
+	println!(r"cargo:rerun-if-changed=src/kv.rs");
+	println!(r"cargo:rerun-if-changed=src/layout.rs");
 	println!(r"cargo:rerun-if-changed=src/lib.rs");
+	println!(r"cargo:rerun-if-changed=src/lint.rs");
+	println!(r"cargo:rerun-if-changed=src/natives.rs");
+	println!(r"cargo:rerun-if-changed=src/pdbinfo.rs");
+	println!(r"cargo:rerun-if-changed=src/policy.rs");
+	println!(r"cargo:rerun-if-changed=src/queue.rs");
+	println!(r"cargo:rerun-if-changed=src/relay.rs");
+	println!(r"cargo:rerun-if-changed=src/replay.rs");
+	println!(r"cargo:rerun-if-changed=src/resolver.rs");
+	println!(r"cargo:rerun-if-changed=src/scheduler.rs");
 	println!(r"cargo:rerun-if-changed=src/script.rs");
+	println!(r"cargo:rerun-if-changed=src/signature.rs");
+	println!(r"cargo:rerun-if-changed=src/twitch.rs");
 	println!(r"cargo:rerun-if-changed=src/window.rs");
 }
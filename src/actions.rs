@@ -0,0 +1,116 @@
+use std::{collections::BTreeMap, error::Error, path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{LCTwitch, args, http::{self, ErrorCode}, policy::Origin};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TimerConfig {
+    pub action: String,
+    // Seconds between two runs.
+    pub interval: u64,
+    #[serde(default)]
+    pub args: Map<String, Value>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChaosEntry {
+    pub action: String,
+    pub weight: u32,
+    #[serde(default)]
+    pub args: Map<String, Value>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChaosConfig {
+    pub interval: u64,
+    pub table: Vec<ChaosEntry>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Action {
+    #[serde(flatten)]
+    pub script: http::Script,
+    // Requests from clients wait in the approval queue. Timers and chaos mode run the action right away.
+    #[serde(default)]
+    pub requires_approval: bool
+}
+
+// The actions clients and the automation can run by name, e.g. {"actions": {"spawn_monster": {"script": "..."}}}.
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct ActionsFile {
+    #[serde(default)]
+    pub actions: BTreeMap<String, Action>,
+    #[serde(default)]
+    pub timers: Vec<TimerConfig>,
+    #[serde(default)]
+    pub chaos: Option<ChaosConfig>
+}
+
+impl ActionsFile {
+    pub fn load(path: &Path) -> Result<ActionsFile, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(ActionsFile::default());
+        }
+
+        let file = serde_json::from_slice::<ActionsFile>(&std::fs::read(path)?)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+
+        file.validate().map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let check_action = |action: &str| if self.actions.contains_key(action) { Ok(()) } else { Err(format!("Unknown action {}", action)) };
+
+        for timer in &self.timers {
+            check_action(&timer.action)?;
+            if timer.interval == 0 {
+                return Err(format!("Timer for {} has no interval", timer.action).into());
+            }
+        }
+
+        if let Some(chaos) = &self.chaos {
+            if chaos.interval == 0 {
+                return Err("Chaos mode has no interval".into());
+            }
+
+            for entry in &chaos.table {
+                check_action(&entry.action)?;
+            }
+
+            if chaos.table.iter().all(|entry| entry.weight == 0) {
+                return Err("Chaos table has no entry with a weight above 0".into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Binds the arguments of an action from the catalog. Arguments given here override the ones of the action.
+pub fn prepare(instance: &LCTwitch, name: &str, args: &Map<String, Value>) -> Result<(String, http::ScriptOptions), http::Error> {
+    let action = instance.actions().actions.get(name).ok_or(ErrorCode::UnknownAction)?;
+
+    let mut merged = action.script.args.clone();
+    merged.extend(args.iter().map(|(key, value)| (key.clone(), value.clone())));
+
+    Ok((args::bind(&action.script.script, &merged)?, action.script.options.clone()))
+}
+
+pub fn requires_approval(instance: &LCTwitch, name: &str) -> bool {
+    instance.actions().actions.get(name).map_or(false, |action| action.requires_approval)
+}
+
+pub async fn run(instance: Arc<LCTwitch>, name: &str, args: &Map<String, Value>, origin: Origin) -> Result<String, http::Error> {
+    // Failures after this are refunded by run_script.
+    let prepared = prepare(&instance, name, args).and_then(|(script_text, options)| Ok((script_text, options, instance.script()?)));
+    let (script_text, options, script_engine) = prepared.map_err(|e| {
+        instance.helix().resolve(&origin, false);
+        e
+    })?;
+    let origin = Origin { action: Some(name.to_owned()), ..origin };
+
+    script_engine.run_script(&instance, &script_text, &options, &origin).await.map_err(http::Error::from)
+}
\ No newline at end of file
@@ -0,0 +1,78 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{policy::Origin, scheduler::now};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PendingApproval {
+    pub id: u64,
+    // Passed back in events, e.g. the id of the channel point redemption to refund if the item is rejected.
+    pub reference: Option<String>,
+    pub queued_at: u64,
+    pub origin: Origin,
+    pub action: String,
+    pub args: Map<String, Value>
+}
+
+// Actions from the catalog marked requires_approval wait here until a moderator approves or rejects them.
+pub struct ApprovalQueue {
+    items: Mutex<VecDeque<PendingApproval>>,
+    next_id: Mutex<u64>
+}
+
+impl ApprovalQueue {
+    pub fn new() -> ApprovalQueue {
+        ApprovalQueue {
+            items: Mutex::new(VecDeque::new()),
+            next_id: Mutex::new(0)
+        }
+    }
+
+    pub fn submit(&self, action: String, args: Map<String, Value>, origin: Origin, reference: Option<String>) -> PendingApproval {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+
+        let item = PendingApproval {
+            id,
+            reference,
+            queued_at: now(),
+            origin,
+            action,
+            args
+        };
+
+        self.items.lock().unwrap().push_back(item.clone());
+        item
+    }
+
+    pub fn items(&self) -> Vec<PendingApproval> {
+        self.items.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<PendingApproval> {
+        self.items.lock().unwrap().iter().find(|item| item.id == id).cloned()
+    }
+
+    // Replaces the arguments given by the viewer. The caller checks them against the action first.
+    pub fn set_args(&self, id: u64, args: Map<String, Value>) -> Option<PendingApproval> {
+        let mut items = self.items.lock().unwrap();
+        let item = items.iter_mut().find(|item| item.id == id)?;
+        item.args = args;
+        Some(item.clone())
+    }
+
+    pub fn take(&self, id: u64) -> Option<PendingApproval> {
+        let mut items = self.items.lock().unwrap();
+        let index = items.iter().position(|item| item.id == id)?;
+        items.remove(index)
+    }
+
+    pub fn take_all(&self) -> Vec<PendingApproval> {
+        self.items.lock().unwrap().drain(..).collect()
+    }
+}
\ No newline at end of file
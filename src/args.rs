@@ -0,0 +1,145 @@
+use std::iter::Peekable;
+
+use serde_json::{Map, Value};
+
+use crate::{http::{Error, ErrorCode}, lint::{self, Token, TokenKind}};
+
+const KEYWORDS: &[&str] = &[
+    "if", "else", "while", "for", "in", "do", "return", "break", "continue", "var", "local", "static", "global", "const",
+    "func", "public", "private", "protected", "new", "this", "true", "false", "nil", "par", "goto", "any", "int", "bool",
+    "string", "object", "array", "map", "id", "dword", "reference"
+];
+
+fn invalid(path: &str, message: &str) -> Error {
+    Error {
+        code: ErrorCode::InvalidArguments,
+        message: format!("{}: {}", path, message)
+    }
+}
+
+fn string_literal(value: &str, path: &str) -> Result<String, Error> {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                literal.push('\\');
+                literal.push(c);
+            },
+            // The engine has no escape sequences for these, and a raw line break would end up in the replay.
+            c if c.is_control() => return Err(invalid(path, "strings must not contain control characters")),
+            c => literal.push(c)
+        }
+    }
+
+    literal.push('"');
+    Ok(literal)
+}
+
+// Converts a JSON value into a C4Script literal that evaluates to the same value.
+pub fn to_literal(value: &Value, path: &str) -> Result<String, Error> {
+    match value {
+        Value::Null => Ok("nil".to_owned()),
+        Value::Bool(value) => Ok(value.to_string()),
+        Value::Number(number) => number.as_i64()
+            .and_then(|number| i32::try_from(number).ok())
+            .map(|number| number.to_string())
+            .ok_or_else(|| invalid(path, "only 32-bit integers are supported")),
+        Value::String(value) => string_literal(value, path),
+        Value::Array(values) => {
+            let elements = values.iter()
+                .enumerate()
+                .map(|(index, value)| to_literal(value, &format!("{}[{}]", path, index)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(format!("[{}]", elements.join(", ")))
+        },
+        Value::Object(values) => {
+            let entries = values.iter()
+                .map(|(key, value)| Ok(format!("{} = {}", string_literal(key, path)?, to_literal(value, &format!("{}.{}", path, key))?)))
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            Ok(format!("{{{}}}", entries.join(", ")))
+        }
+    }
+}
+
+fn parse_literal<'a>(tokens: &mut Peekable<impl Iterator<Item = Token<'a>>>) -> Option<Value> {
+    let token = tokens.next()?;
+
+    match (token.kind, token.text) {
+        (TokenKind::Keyword, "nil") => Some(Value::Null),
+        (TokenKind::Keyword, "true") => Some(Value::Bool(true)),
+        (TokenKind::Keyword, "false") => Some(Value::Bool(false)),
+        (TokenKind::Number, number) => number.parse::<i32>().ok().map(Value::from),
+        (TokenKind::Operator, "-") => tokens.next()
+            .filter(|token| token.kind == TokenKind::Number)
+            .and_then(|token| format!("-{}", token.text).parse::<i32>().ok())
+            .map(Value::from),
+        (TokenKind::String, string) => Some(Value::String(lint::unescape(string))),
+        (TokenKind::Operator, "[") => {
+            let mut values = Vec::new();
+
+            while tokens.next_if(|token| token.text == "]").is_none() {
+                values.push(parse_literal(tokens)?);
+                tokens.next_if(|token| token.text == ",");
+            }
+
+            Some(Value::Array(values))
+        },
+        (TokenKind::Operator, "{") => {
+            let mut values = Map::new();
+
+            while tokens.next_if(|token| token.text == "}").is_none() {
+                let key = match tokens.next()? {
+                    Token { kind: TokenKind::Identifier | TokenKind::Keyword, text, .. } => text.to_owned(),
+                    Token { kind: TokenKind::String, text, .. } => lint::unescape(text),
+                    _ => return None
+                };
+
+                tokens.next_if(|token| token.text == "=")?;
+                values.insert(key, parse_literal(tokens)?);
+                tokens.next_if(|token| token.text == ",");
+            }
+
+            Some(Value::Object(values))
+        },
+        _ => None
+    }
+}
+
+// Converts a value as the console prints it back into JSON. Values JSON has no equivalent for,
+// e.g. objects and IDs, are passed on as their text.
+pub fn from_literal(text: &str) -> Value {
+    lint::tokenize(text).ok()
+        .and_then(|tokens| {
+            let mut tokens = tokens.into_iter().peekable();
+            parse_literal(&mut tokens).filter(|_| tokens.peek().is_none())
+        })
+        .unwrap_or_else(|| Value::String(text.to_owned()))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.iter().any(|keyword| keyword.eq_ignore_ascii_case(name))
+}
+
+// Declares every argument as a variable holding its literal before the script, e.g. var amount = 5;. The script
+// itself is left untouched and never rebuilt from the values, so an argument cannot change its structure.
+pub fn bind(script: &str, args: &Map<String, Value>) -> Result<String, Error> {
+    let mut result = String::new();
+
+    for (name, value) in args {
+        if !is_identifier(name) {
+            return Err(invalid(&format!("args.{}", name), "not a valid variable name"));
+        }
+
+        result.push_str(&format!("var {} = {}; ", name, to_literal(value, &format!("args.{}", name))?));
+    }
+
+    result.push_str(script);
+    Ok(result)
+}
\ No newline at end of file
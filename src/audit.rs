@@ -0,0 +1,169 @@
+use std::{error::Error, fs::{File, OpenOptions}, io::{BufRead, BufReader, Write}, path::{Path, PathBuf}, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{http::{ErrorCode, ScriptResult}, policy::{Origin, Source}};
+
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct AuditEntry {
+    // Milliseconds since the Unix epoch, when the script was requested.
+    pub time: u64,
+    // Read once the script has run, so it is the frame it ran in unless the game was faster than LCTwitch.
+    pub frame: Option<i32>,
+    pub origin: Origin,
+    pub script: String,
+    #[serde(flatten)]
+    pub result: ScriptResult,
+    // Milliseconds from the request until the result, including the wait for the next frame.
+    pub duration: u64
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct AuditQuery {
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub source: Option<Source>,
+    #[serde(default)]
+    pub action: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub reference: Option<String>,
+    // Milliseconds since the Unix epoch, both inclusive.
+    #[serde(default)]
+    pub since: Option<u64>,
+    #[serde(default)]
+    pub until: Option<u64>,
+    // true only returns failed scripts, false only successful ones.
+    #[serde(default)]
+    pub failed: Option<bool>,
+    #[serde(default)]
+    pub error: Option<ErrorCode>,
+    // Matches scripts containing this text.
+    #[serde(default)]
+    pub script: Option<String>,
+    // The newest entries are returned, oldest first.
+    #[serde(default)]
+    pub limit: Option<usize>
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let error = entry.result.error.as_ref().map(|error| error.code as u8);
+
+        (self.token.is_none() || self.token == entry.origin.token)
+            && self.source.map_or(true, |source| source == entry.origin.source)
+            && (self.action.is_none() || self.action == entry.origin.action)
+            && (self.user.is_none() || self.user == entry.origin.user)
+            && (self.reference.is_none() || self.reference == entry.origin.reference)
+            && self.since.map_or(true, |since| entry.time >= since)
+            && self.until.map_or(true, |until| entry.time <= until)
+            && self.failed.map_or(true, |failed| failed == error.is_some())
+            && self.error.map_or(true, |code| Some(code as u8) == error)
+            && self.script.as_ref().map_or(true, |script| entry.script.contains(script.as_str()))
+    }
+}
+
+// An append-only JSON Lines file of every script that was run or refused. Once the file would grow beyond
+// max_size, it is renamed to e.g. LCTwitch.audit.1.jsonl, shifting older files up to max_files.
+pub struct AuditLog {
+    file: PathBuf,
+    max_size: u64,
+    max_files: u32,
+    writer: Mutex<Option<File>>
+}
+
+impl AuditLog {
+    pub fn new(file: PathBuf, max_size: u64, max_files: u32) -> AuditLog {
+        AuditLog {
+            file,
+            max_size,
+            max_files,
+            writer: Mutex::new(None)
+        }
+    }
+
+    fn rotated_path(path: &Path, index: u32) -> PathBuf {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => path.with_extension(format!("{}.{}", index, extension)),
+            None => path.with_extension(index.to_string())
+        }
+    }
+
+    fn rotate(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if self.max_files == 0 {
+            return Ok(std::fs::remove_file(path)?);
+        }
+
+        let oldest = Self::rotated_path(path, self.max_files);
+        if oldest.exists() {
+            std::fs::remove_file(oldest)?;
+        }
+
+        for index in (1..self.max_files).rev() {
+            let rotated = Self::rotated_path(path, index);
+            if rotated.exists() {
+                std::fs::rename(rotated, Self::rotated_path(path, index + 1))?;
+            }
+        }
+
+        Ok(std::fs::rename(path, Self::rotated_path(path, 1))?)
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> Result<(), Box<dyn Error>> {
+        let path = &self.file;
+
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().unwrap();
+
+        let size = match writer.as_ref() {
+            Some(file) => file.metadata()?.len(),
+            None => path.metadata().map_or(0, |metadata| metadata.len())
+        };
+
+        if size > 0 && size + line.len() as u64 > self.max_size {
+            *writer = None;
+            self.rotate(path)?;
+        }
+
+        if writer.is_none() {
+            *writer = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+
+        if let Some(file) = writer.as_mut() {
+            file.write_all(&line)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, Box<dyn Error>> {
+        let path = &self.file;
+
+        // Keeps the files from being rotated while they are read.
+        let _writer = self.writer.lock().unwrap();
+
+        let mut paths = (1..=self.max_files).rev().map(|index| Self::rotated_path(path, index)).collect::<Vec<_>>();
+        paths.push(path.clone());
+
+        let mut entries = Vec::new();
+        for path in paths.iter().filter(|path| path.exists()) {
+            for line in BufReader::new(File::open(path)?).lines() {
+                // A line cut off by a crash is skipped rather than hiding everything after it.
+                if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line?) {
+                    if query.matches(&entry) {
+                        entries.push(entry);
+                    }
+                }
+            }
+        }
+
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        Ok(entries.split_off(entries.len().saturating_sub(limit)))
+    }
+}
\ No newline at end of file
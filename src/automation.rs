@@ -0,0 +1,122 @@
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+
+use rand::distributions::{Distribution, WeightedIndex};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{LCTwitch, actions, events::Event, policy::{Origin, Source}};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationSource {
+    Timer,
+    Chaos
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseTarget {
+    #[default]
+    All,
+    Timers,
+    Chaos
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct AutomationStatus {
+    pub timers_paused: bool,
+    pub chaos_paused: bool,
+    pub timers: usize,
+    pub chaos: bool
+}
+
+pub struct Automation {
+    timers_paused: AtomicBool,
+    chaos_paused: AtomicBool
+}
+
+impl Automation {
+    pub fn new() -> Automation {
+        Automation {
+            timers_paused: AtomicBool::new(false),
+            chaos_paused: AtomicBool::new(false)
+        }
+    }
+
+    pub fn set_paused(&self, target: PauseTarget, paused: bool) {
+        if let PauseTarget::All | PauseTarget::Timers = target {
+            self.timers_paused.store(paused, Ordering::SeqCst);
+        }
+
+        if let PauseTarget::All | PauseTarget::Chaos = target {
+            self.chaos_paused.store(paused, Ordering::SeqCst);
+        }
+    }
+
+    pub fn status(&self, instance: &LCTwitch) -> AutomationStatus {
+        AutomationStatus {
+            timers_paused: self.timers_paused.load(Ordering::SeqCst),
+            chaos_paused: self.chaos_paused.load(Ordering::SeqCst),
+            timers: instance.actions().timers.len(),
+            chaos: instance.actions().chaos.is_some()
+        }
+    }
+}
+
+async fn execute(instance: Arc<LCTwitch>, source: AutomationSource, action: String, args: Map<String, Value>) {
+    let origin = Origin::new(match source { AutomationSource::Timer => Source::Timer, AutomationSource::Chaos => Source::Chaos }, None);
+    let result = actions::run(instance.clone(), &action, &args, origin).await;
+
+    instance.events().publish(Event::Automation {
+        source,
+        action,
+        result: result.into()
+    });
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let file = instance.actions();
+    if file.timers.is_empty() && file.chaos.is_none() {
+        return;
+    }
+
+    let start = Instant::now();
+    let mut timers_due = file.timers.iter().map(|timer| start + Duration::from_secs(timer.interval)).collect::<Vec<_>>();
+    let mut chaos_due = file.chaos.as_ref().map(|chaos| start + Duration::from_secs(chaos.interval));
+
+    let weights = file.chaos.as_ref().and_then(|chaos| WeightedIndex::new(chaos.table.iter().map(|entry| entry.weight)).ok());
+
+    let mut interval = tokio::time::interval(Duration::from_millis(250));
+
+    loop {
+        interval.tick().await;
+        let now = Instant::now();
+
+        // Ticks that come due while no scenario is running or while paused are skipped, not caught up on later.
+        let running = instance.script().map_or(false, |script| script.is_running()) && instance.kill_switch().check().is_ok();
+        let automation = instance.automation();
+
+        for (timer, due) in file.timers.iter().zip(timers_due.iter_mut()) {
+            if *due > now {
+                continue;
+            }
+
+            *due = now + Duration::from_secs(timer.interval);
+
+            if running && !automation.timers_paused.load(Ordering::SeqCst) {
+                tokio::spawn(execute(instance.clone(), AutomationSource::Timer, timer.action.clone(), timer.args.clone()));
+            }
+        }
+
+        if let (Some(chaos), Some(due), Some(weights)) = (&file.chaos, chaos_due.as_mut(), &weights) {
+            if *due <= now {
+                *due = now + Duration::from_secs(chaos.interval);
+
+                if running && !automation.chaos_paused.load(Ordering::SeqCst) {
+                    let entry = &chaos.table[weights.sample(&mut rand::thread_rng())];
+                    tokio::spawn(execute(instance.clone(), AutomationSource::Chaos, entry.action.clone(), entry.args.clone()));
+                }
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,382 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+#[path = "../environment.rs"]
+mod environment;
+
+const GAME_EXECUTABLE_NAMES: &[&str] = &["clonk", "legacyclonk"];
+const LIBRARY_NAME: &str = "libfmod64.so";
+
+// Everything Script::new, Natives::new and LCTwitch::new look up by name.
+const REQUIRED_SYMBOLS: &[&str] = &[
+    "Log",
+    "C4ControlPacket::C4ControlPacket",
+    "StdStrBuf::Copy",
+    "C4GameControl::DoInput",
+    "C4ControlScript::`vftable'",
+    "C4AulScript::DirectExec",
+    "C4Value::GetDataString",
+    "C4Value::~C4Value",
+    "StdStrBuf::~StdStrBuf",
+    "C4PlayerList::Get",
+    "C4GameObjects::SafeObjectPointer",
+    "C4Game::Execute",
+    "InitFunctionMap",
+    "C4AulFunc::C4AulFunc"
+];
+
+struct Options {
+    game: Option<PathBuf>,
+    library: Option<PathBuf>,
+    settings: Vec<(String, String)>,
+    check_symbols: bool,
+    game_arguments: Vec<OsString>
+}
+
+fn usage() -> String {
+    format!(
+        "Usage: {} [--game <path>] [--library <path>] [--port <port>] [--set <Setting>=<value>] [--skip-symbol-check] [-- <game arguments>...]",
+        std::env::args().next().unwrap_or_else(|| "lctwitch-launch".to_owned())
+    )
+}
+
+fn parse_options() -> Result<Options, Box<dyn Error>> {
+    let mut options = Options {
+        game: std::env::var_os("LCTWITCH_GAME").map(PathBuf::from),
+        library: std::env::var_os("LCTWITCH_LIBRARY").map(PathBuf::from),
+        settings: Vec::new(),
+        check_symbols: true,
+        game_arguments: Vec::new()
+    };
+
+    let mut args = std::env::args_os().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("Missing value for {}\n{}", arg.to_string_lossy(), usage()));
+
+        match arg.to_str() {
+            Some("--game") => options.game = Some(value()?.into()),
+            Some("--library") => options.library = Some(value()?.into()),
+            Some("--port") => {
+                let port = value()?.to_string_lossy().parse::<u16>()?;
+                options.settings.push(("HttpServerPort".to_owned(), port.to_string()));
+            },
+            Some("--set") => {
+                let setting = value()?.to_string_lossy().into_owned();
+                let (name, value) = setting.split_once('=').ok_or_else(|| format!("Expected <Setting>=<value>, got {}", setting))?;
+                options.settings.push((name.to_owned(), value.to_owned()));
+            },
+            Some("--skip-symbol-check") => options.check_symbols = false,
+            Some("--help") | Some("-h") => {
+                println!("{}", usage());
+                std::process::exit(0);
+            },
+            Some("--") => {
+                options.game_arguments.extend(args);
+                break;
+            },
+            _ => options.game_arguments.push(arg)
+        }
+    }
+
+    Ok(options)
+}
+
+fn find_game(options: &Options) -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(game) = &options.game {
+        return Ok(game.clone());
+    }
+
+    let current_dir = std::env::current_dir()?;
+    let search_path = std::iter::once(current_dir)
+        .chain(std::env::var_os("PATH").map(|path| std::env::split_paths(&path).collect::<Vec<_>>()).unwrap_or_default());
+
+    for directory in search_path {
+        for name in GAME_EXECUTABLE_NAMES {
+            let candidate = directory.join(name);
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+        }
+    }
+
+    Err("Could not find the LegacyClonk executable, pass it with --game".into())
+}
+
+fn find_library(options: &Options) -> Result<PathBuf, Box<dyn Error>> {
+    let library = match &options.library {
+        Some(library) => library.clone(),
+        None => std::env::current_exe()?.parent().ok_or("Launcher has no parent directory")?.join(LIBRARY_NAME)
+    };
+
+    if !library.is_file() {
+        return Err(format!("Could not find {}, pass it with --library", library.display()).into());
+    }
+
+    // The dynamic loader resolves relative LD_PRELOAD entries against the game's working directory.
+    Ok(library.canonicalize()?)
+}
+
+fn source_name(name: &[u8]) -> String {
+    let mut result = String::new();
+    for component in name.split(|&c| c == b':').filter(|c| !c.is_empty()) {
+        let component = String::from_utf8_lossy(component);
+        result.push_str(&component.len().to_string());
+        result.push_str(&component);
+    }
+
+    result
+}
+
+// Returns the prefixes of the Itanium-mangled names a symbol may have, without the parameter list.
+fn mangled_prefixes(symbol: &str) -> Vec<String> {
+    let Some((class, member)) = symbol.rsplit_once("::") else {
+        return vec![symbol.to_owned(), format!("_Z{}{}", symbol.len(), symbol)];
+    };
+
+    let class_name = source_name(class.as_bytes());
+
+    if member == "`vftable'" {
+        return vec![format!("_ZTV{}", class_name)];
+    }
+
+    let member = if member == class.rsplit("::").next().unwrap_or(class) {
+        "C".to_owned()
+    }
+    else if member.starts_with('~') {
+        "D".to_owned()
+    }
+    else {
+        format!("{}{}", member.len(), member)
+    };
+
+    vec![format!("_ZN{}{}", class_name, member), format!("_ZNK{}{}", class_name, member)]
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset.checked_add(N)?).map(|bytes| bytes.try_into().unwrap())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    read_bytes(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    read_bytes(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    read_bytes(data, offset).map(u64::from_le_bytes)
+}
+
+// Offsets and sizes taken from the file, which may be malformed.
+fn read_offset(data: &[u8], offset: usize) -> Option<usize> {
+    read_u64(data, offset).and_then(|value| usize::try_from(value).ok())
+}
+
+fn defined_symbols(data: &[u8]) -> Result<Vec<&[u8]>, Box<dyn Error>> {
+    const SHT_SYMTAB: u32 = 2;
+    const SHT_DYNSYM: u32 = 11;
+    const SYMBOL_SIZE: usize = 24;
+
+    if data.get(0..5) != Some(b"\x7fELF\x02".as_slice()) {
+        return Err("Not a 64-bit ELF executable".into());
+    }
+
+    let truncated = || "Truncated ELF executable";
+
+    let section_headers = read_offset(data, 0x28).ok_or_else(truncated)?;
+    let section_header_size = read_u16(data, 0x3A).ok_or_else(truncated)? as usize;
+    let section_count = read_u16(data, 0x3C).ok_or_else(truncated)? as usize;
+
+    let section = |index: usize| index.checked_mul(section_header_size)
+        .and_then(|offset| offset.checked_add(section_headers))
+        .ok_or_else(truncated);
+    let field = |header: usize, offset: usize| header.checked_add(offset).ok_or_else(truncated);
+
+    let mut symbols = Vec::new();
+
+    for index in 0..section_count {
+        let header = section(index)?;
+        let section_type = read_u32(data, field(header, 4)?).ok_or_else(truncated)?;
+        if section_type != SHT_SYMTAB && section_type != SHT_DYNSYM {
+            continue;
+        }
+
+        let offset = read_offset(data, field(header, 0x18)?).ok_or_else(truncated)?;
+        let size = read_offset(data, field(header, 0x20)?).ok_or_else(truncated)?;
+        let end = offset.checked_add(size).ok_or_else(truncated)?;
+        let strings_header = section(read_u32(data, field(header, 0x28)?).ok_or_else(truncated)? as usize)?;
+        let strings_offset = read_offset(data, field(strings_header, 0x18)?).ok_or_else(truncated)?;
+        let strings = data.get(strings_offset..).ok_or_else(truncated)?;
+
+        for symbol in (offset..end).step_by(SYMBOL_SIZE) {
+            let name = read_u32(data, symbol).ok_or_else(truncated)? as usize;
+            let section_index = read_u16(data, field(symbol, 6)?).ok_or_else(truncated)?;
+            if name == 0 || section_index == 0 {
+                continue;
+            }
+
+            let name = strings.get(name..).ok_or_else(truncated)?;
+            symbols.push(name.split(|&c| c == 0).next().unwrap_or_default());
+        }
+    }
+
+    Ok(symbols)
+}
+
+fn check_symbols(game: &Path) -> Result<(), Box<dyn Error>> {
+    let data = std::fs::read(game)?;
+    let symbols = defined_symbols(&data)?;
+
+    let missing = REQUIRED_SYMBOLS.iter()
+        .filter(|symbol| {
+            let prefixes = mangled_prefixes(symbol);
+            !symbols.iter().any(|name| prefixes.iter().any(|prefix| {
+                // Plain names (global variables) have to match exactly.
+                if prefix.starts_with("_Z") { name.starts_with(prefix.as_bytes()) } else { *name == prefix.as_bytes() }
+            }))
+        })
+        .copied()
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(format!("{} is missing symbols required by LCTwitch (is it stripped?): {}", game.display(), missing.join(", ")).into())
+    }
+}
+
+#[cfg(unix)]
+fn launch(game: &Path, library: &Path, options: Options) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::process::CommandExt;
+
+    let mut preload = library.as_os_str().to_owned();
+    if let Some(existing) = std::env::var_os("LD_PRELOAD").filter(|existing| !existing.is_empty()) {
+        preload.push(":");
+        preload.push(existing);
+    }
+
+    let mut command = std::process::Command::new(game);
+    command.args(&options.game_arguments)
+        .env("LD_PRELOAD", preload);
+
+    if let Some(directory) = game.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+        command.current_dir(directory);
+    }
+
+    for (name, value) in &options.settings {
+        command.env(environment::variable_name(name), value);
+    }
+
+    Err(command.exec().into())
+}
+
+#[cfg(not(unix))]
+fn launch(_game: &Path, _library: &Path, _options: Options) -> Result<(), Box<dyn Error>> {
+    Err("lctwitch-launch is only supported on Linux".into())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_options()?;
+    let game = find_game(&options)?.canonicalize()?;
+    let library = find_library(&options)?;
+
+    if options.check_symbols {
+        check_symbols(&game)?;
+    }
+
+    launch(&game, &library, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTION_HEADER_SIZE: usize = 0x40;
+
+    fn write(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // A file with a null section, a symbol table and its string table. Symbols with section index 0 are undefined.
+    fn elf(symbols: &[(&str, u16)]) -> Vec<u8> {
+        let mut strings = vec![0];
+        let mut table = vec![0; 24];
+
+        for (name, section_index) in symbols {
+            let mut symbol = [0; 24];
+            write(&mut symbol, 0, &(strings.len() as u32).to_le_bytes());
+            write(&mut symbol, 6, &section_index.to_le_bytes());
+            table.extend_from_slice(&symbol);
+
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+
+        let strings_offset = 0x40;
+        let table_offset = strings_offset + strings.len();
+        let section_headers = table_offset + table.len();
+
+        let mut data = vec![0; section_headers + 3 * SECTION_HEADER_SIZE];
+        write(&mut data, 0, b"\x7fELF\x02");
+        write(&mut data, 0x28, &(section_headers as u64).to_le_bytes());
+        write(&mut data, 0x3A, &(SECTION_HEADER_SIZE as u16).to_le_bytes());
+        write(&mut data, 0x3C, &3u16.to_le_bytes());
+        write(&mut data, strings_offset, &strings);
+        write(&mut data, table_offset, &table);
+
+        let symtab = section_headers + SECTION_HEADER_SIZE;
+        write(&mut data, symtab + 4, &2u32.to_le_bytes());
+        write(&mut data, symtab + 0x18, &(table_offset as u64).to_le_bytes());
+        write(&mut data, symtab + 0x20, &(table.len() as u64).to_le_bytes());
+        write(&mut data, symtab + 0x28, &2u32.to_le_bytes());
+
+        let strtab = symtab + SECTION_HEADER_SIZE;
+        write(&mut data, strtab + 4, &3u32.to_le_bytes());
+        write(&mut data, strtab + 0x18, &(strings_offset as u64).to_le_bytes());
+        write(&mut data, strtab + 0x20, &(strings.len() as u64).to_le_bytes());
+
+        data
+    }
+
+    #[test]
+    fn mangles_functions_and_globals() {
+        assert_eq!(mangled_prefixes("Game"), ["Game", "_Z4Game"]);
+        assert_eq!(mangled_prefixes("C4AulScript::DirectExec"), ["_ZN11C4AulScript10DirectExec", "_ZNK11C4AulScript10DirectExec"]);
+        assert_eq!(mangled_prefixes("C4ControlPacket::C4ControlPacket"), ["_ZN15C4ControlPacketC", "_ZNK15C4ControlPacketC"]);
+        assert_eq!(mangled_prefixes("StdStrBuf::~StdStrBuf"), ["_ZN9StdStrBufD", "_ZNK9StdStrBufD"]);
+        assert_eq!(mangled_prefixes("C4ControlScript::`vftable'"), ["_ZTV15C4ControlScript"]);
+    }
+
+    #[test]
+    fn converts_setting_names() {
+        assert_eq!(environment::variable_name("HttpServerPort"), "LCTWITCH_HTTP_SERVER_PORT");
+    }
+
+    #[test]
+    fn reads_defined_symbols() {
+        let data = elf(&[("Game", 5), ("_ZN9StdStrBufD2Ev", 7), ("malloc", 0)]);
+        assert_eq!(defined_symbols(&data).unwrap(), [b"Game".as_slice(), b"_ZN9StdStrBufD2Ev".as_slice()]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(defined_symbols(b"MZ\x90\x00").is_err());
+
+        let data = elf(&[("Game", 5)]);
+        assert!(defined_symbols(&data[..data.len() - SECTION_HEADER_SIZE]).is_err());
+
+        let mut data = elf(&[("Game", 5)]);
+        write(&mut data, 0x28, &u64::MAX.to_le_bytes());
+        assert!(defined_symbols(&data).is_err());
+
+        let mut data = elf(&[("Game", 5)]);
+        let symtab = data.len() - 2 * SECTION_HEADER_SIZE;
+        write(&mut data, symtab + 0x18, &(u64::MAX - 8).to_le_bytes());
+        write(&mut data, symtab + 0x20, &u64::MAX.to_le_bytes());
+        assert!(defined_symbols(&data).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+#[path = "../pdbinfo.rs"]
+#[allow(dead_code)]
+mod pdbinfo;
+
+use pdbinfo::{PdbInfo, TypeLayout};
+
+// The types and members Script::new resolves.
+const LCTWITCH_TYPES: &[(&str, &[&str])] = &[
+    ("C4ControlScript", &["iTargetObj", "Strict", "Script"]),
+    ("C4ControlPlayerControl", &["iPlr", "iCom", "iData"]),
+    ("C4ControlMessage", &["eType", "iPlayer", "iToPlayer", "Message"]),
+    ("C4Game", &["IsRunning", "FrameCounter", "ScenarioFilename", "Control", "Network", "Parameters", "ScriptEngine", "Objects", "Players"]),
+    ("C4GameControl", &["eMode", "pRecord"]),
+    ("C4Record", &["sFilename"]),
+    ("C4GameParameters", &["LeagueAddress"]),
+    ("C4Config", &["General"]),
+    ("C4ConfigGeneral", &["AllowScriptingInReplays"]),
+    ("C4Network2", &["fHost", "Status"]),
+    ("C4Network2Status", &["eState"]),
+    ("C4Player", &["Cursor", "Number", "Name"]),
+    ("C4Object", &["Number", "Owner", "Info"]),
+    ("StdStrBuf", &["fRef", "pData", "iSize"]),
+    ("C4Value", &[]),
+    ("C4AulFunc", &[])
+];
+
+const LCTWITCH_GLOBALS: &[&str] = &["Game", "Config"];
+
+const LCTWITCH_FUNCTIONS: &[&str] = &[
+    "Log",
+    "C4ControlPacket::C4ControlPacket",
+    "StdStrBuf::Copy",
+    "C4GameControl::DoInput",
+    "C4AulScript::DirectExec",
+    "C4Value::GetDataString",
+    "C4Value::~C4Value",
+    "StdStrBuf::~StdStrBuf",
+    "C4Game::Execute",
+    "C4Application::Execute",
+    "C4PlayerList::Get",
+    "C4GameObjects::SafeObjectPointer",
+    "C4AulFunc::C4AulFunc",
+    "InitFunctionMap",
+    "C4PlayerList::Join",
+    "C4Player::Clear",
+    "C4Player::Eliminate",
+    "C4Object::AssignDeath",
+    "C4Game::Init",
+    "C4Game::Clear",
+    "C4Game::DoGameOver"
+];
+
+const LCTWITCH_VTABLES: &[&str] = &["C4ControlScript", "C4ControlPlayerControl", "C4ControlMessage", "C4AulFunc"];
+
+fn print_type(pdb: &PdbInfo, layout: &TypeLayout, members: Option<&[&str]>) -> bool {
+    let mut complete = true;
+
+    println!("{} (size 0x{:X})", layout.name, layout.size);
+
+    for base in &layout.base_classes {
+        println!("  +0x{:04X} base {}", base.offset, base.name);
+    }
+
+    match members {
+        Some(members) => {
+            for name in members {
+                match pdb.member(&layout.name, name) {
+                    Ok(member) => println!("  +0x{:04X} {} : {} ({})", member.offset, member.name, member.type_ref, pdb.size_of(&member.type_ref).map_or_else(|| "?".to_owned(), |size| size.to_string())),
+                    Err(err) => {
+                        println!("  MISSING {}", err);
+                        complete = false;
+                    }
+                }
+            }
+        },
+        None => {
+            for member in &layout.members {
+                println!("  +0x{:04X} {} : {} ({})", member.offset, member.name, member.type_ref, pdb.size_of(&member.type_ref).map_or_else(|| "?".to_owned(), |size| size.to_string()));
+            }
+        }
+    }
+
+    for method in &layout.virtual_methods {
+        println!("  vtable[{}] {}", method.vtable_index, method.name);
+    }
+
+    complete
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = PathBuf::from(args.next().ok_or("Usage: lctwitch-layout <pdb> [type...]")?);
+    let requested = args.collect::<Vec<_>>();
+
+    let pdb = PdbInfo::open(&path)?;
+
+    if !requested.is_empty() {
+        for name in &requested {
+            print_type(&pdb, pdb.type_layout(name)?, None);
+            println!();
+        }
+
+        return Ok(());
+    }
+
+    let mut complete = true;
+
+    for (name, members) in LCTWITCH_TYPES {
+        match pdb.type_layout(name) {
+            Ok(layout) => complete &= print_type(&pdb, layout, Some(members)),
+            Err(err) => {
+                println!("MISSING {}", err);
+                complete = false;
+            }
+        }
+
+        println!();
+    }
+
+    for name in LCTWITCH_GLOBALS {
+        match pdb.global(name) {
+            Ok(global) => println!("{} : {} at {}", name, global.type_ref, global.location),
+            Err(err) => {
+                println!("MISSING {}", err);
+                complete = false;
+            }
+        }
+    }
+
+    for name in LCTWITCH_FUNCTIONS {
+        match pdb.function(name) {
+            Ok(location) => println!("{} at {}", name, location),
+            Err(err) => {
+                println!("MISSING {}", err);
+                complete = false;
+            }
+        }
+    }
+
+    // PDBs without a publics stream (like the test fixture) have no vtable symbols.
+    for name in LCTWITCH_VTABLES {
+        let entries = pdb.vtable_entries(name).map_or_else(|err| err.to_string(), |entries| entries.to_string());
+        let location = pdb.vtable(name).map_or_else(|err| err.to_string(), |location| location.to_string());
+        println!("{}::`vftable' at {}, {} entries", name, location, entries);
+    }
+
+    if complete {
+        Ok(())
+    }
+    else {
+        Err("Some types or members required by LCTwitch are missing".into())
+    }
+}
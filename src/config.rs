@@ -1,19 +1,103 @@
+use std::{collections::BTreeSet, error::Error, mem::MaybeUninit, net::{IpAddr, Ipv4Addr}, path::{Path, PathBuf}};
+use windows::{core::{w, HSTRING}, Win32::System::Registry::{RegCloseKey,RegOpenKeyExW, RegQueryValueExW, HKEY_CURRENT_USER, HKEY, KEY_READ, REG_SZ, REG_VALUE_TYPE}};
 
-use std::{error::Error, mem::MaybeUninit};
-use windows::{core::w, Win32::System::Registry::{RegCloseKey,RegOpenKeyExW, RegQueryValueExW, HKEY_CURRENT_USER, HKEY, KEY_READ}};
+use crate::{environment, lint::LintRules};
 
 pub struct Config {
-    port: u16
+    port: u16,
+    listen_address: IpAddr,
+    tls_certificate_file: Option<PathBuf>,
+    tls_key_file: Option<PathBuf>,
+    layout_snapshot_file: Option<PathBuf>,
+    signature_directory: Option<PathBuf>,
+    default_strictness: u32,
+    default_context: String,
+    schedule_file: Option<PathBuf>,
+    actions_file: Option<PathBuf>,
+    hold_seconds: u32,
+    policy_file: Option<PathBuf>,
+    kv_file: Option<PathBuf>,
+    lint_rules: LintRules,
+    twitch_channel: Option<String>,
+    twitch_user: Option<String>,
+    twitch_token: Option<String>,
+    chat_announcements: BTreeSet<String>,
+    relay_url: Option<String>,
+    relay_token: Option<String>,
+    audit_file: Option<PathBuf>,
+    audit_max_size: u32,
+    audit_files: u32,
+    pause_hotkey: Option<String>,
+    pause_refund: bool,
+    twitch_client_id: Option<String>,
+    helix_token: Option<String>,
+    helix_base_url: String
 }
 
 impl Config {
     pub fn new() -> Result<Config, Box<dyn Error>> {
-        let port = unsafe {
-            Self::read_port_from_registry().ok()
-        }.unwrap_or_else(|| 11116);
-        
+        let port = Self::read_u32("HttpServerPort")
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or_else(|| 11116);
+
+        // e.g. "0.0.0.0", so that the LCTwitch of network clients can relay requests to this one.
+        let listen_address = Self::read_string("HttpListenAddress")
+            .and_then(|address| address.parse::<IpAddr>().ok())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        // PEM files. With both set, the server only accepts HTTPS. Only one of them is a mistake that would otherwise
+        // leave the server unencrypted.
+        let tls_certificate_file = Self::read_string("HttpTlsCertificate").map(PathBuf::from);
+        let tls_key_file = Self::read_string("HttpTlsKey").map(PathBuf::from);
+        if tls_certificate_file.is_some() != tls_key_file.is_some() {
+            return Err("HttpTlsCertificate and HttpTlsKey must be set together".into());
+        }
+
         Ok(Config {
-            port
+            port,
+            listen_address,
+            tls_certificate_file,
+            tls_key_file,
+            layout_snapshot_file: Self::read_string("LayoutSnapshotFile").map(PathBuf::from),
+            signature_directory: Self::read_string("SignatureDirectory").map(PathBuf::from),
+            // Levels the engine does not know are ignored like unset ones.
+            default_strictness: Self::read_u32("DefaultStrictness").filter(|strictness| *strictness <= 3).unwrap_or(3),
+            default_context: Self::read_string("DefaultContext").unwrap_or_else(|| "LCTwitch".to_owned()),
+            schedule_file: Self::read_string("ScheduleFile").map(PathBuf::from),
+            actions_file: Self::read_string("ActionsFile").map(PathBuf::from),
+            // 0 rejects requests while no scenario is running instead of holding them.
+            hold_seconds: Self::read_u32("HoldSeconds").unwrap_or(0),
+            policy_file: Self::read_string("PolicyFile").map(PathBuf::from),
+            kv_file: Self::read_string("KvFile").map(PathBuf::from),
+            lint_rules: LintRules {
+                max_length: Self::read_u32("MaxScriptLength").map(|length| length as usize),
+                max_loops: Self::read_u32("MaxLoops").map(|loops| loops as usize),
+                allowed_functions: Self::read_list("AllowedFunctions"),
+                denied_functions: Self::read_list("DeniedFunctions").unwrap_or_default()
+            },
+            twitch_channel: Self::read_string("TwitchChannel"),
+            twitch_user: Self::read_string("TwitchUser"),
+            // An OAuth token with the chat:read and chat:edit scopes, with or without the "oauth:" prefix.
+            twitch_token: Self::read_string("TwitchToken"),
+            // Event types to announce in chat, e.g. "player_joined, round_ended".
+            chat_announcements: Self::read_list("ChatAnnouncements").unwrap_or_default(),
+            // The host's LCTwitch, e.g. "https://192.168.0.2:11116", and a token its policy file lists as a relay token.
+            relay_url: Self::read_string("RelayUrl"),
+            relay_token: Self::read_string("RelayToken"),
+            audit_file: Self::read_string("AuditFile").map(PathBuf::from),
+            // In bytes. Rotated files beyond AuditFiles are deleted.
+            audit_max_size: Self::read_u32("AuditMaxSize").unwrap_or(10 * 1024 * 1024),
+            audit_files: Self::read_u32("AuditFiles").unwrap_or(5),
+            // e.g. "Ctrl+Shift+F12". Only available with a game window.
+            pause_hotkey: Self::read_string("PauseHotkey"),
+            // Whether held redemptions are refunded when the hotkey or a moderator pauses LCTwitch.
+            pause_refund: Self::read_u32("PauseRefund").map_or(false, |refund| refund != 0),
+            // The client id the tokens were issued to. Without it, redemptions are neither fulfilled nor refunded.
+            twitch_client_id: Self::read_string("TwitchClientId"),
+            // A user token of the broadcaster with the channel:manage:redemptions scope, if TwitchToken lacks it.
+            helix_token: Self::read_string("HelixToken"),
+            // e.g. "http://localhost:8080/mock" to test against the Twitch CLI's mock API.
+            helix_base_url: Self::read_string("HelixBaseUrl").unwrap_or_else(|| "https://api.twitch.tv/helix".to_owned())
         })
     }
 
@@ -21,23 +105,174 @@ impl Config {
         self.port
     }
 
-    unsafe fn read_port_from_registry() -> Result<u16, Box<dyn Error>> {
-        let key = {
-            let mut key = MaybeUninit::<HKEY>::uninit();
-            RegOpenKeyExW(HKEY_CURRENT_USER, w!("Software\\LegacyClonk Team\\LCTwitch"), 0, KEY_READ, key.as_mut_ptr()).ok()?;
-            key.assume_init()
+    pub fn listen_address(&self) -> IpAddr {
+        self.listen_address
+    }
+
+    pub fn tls_files(&self) -> Option<(&Path, &Path)> {
+        self.tls_certificate_file.as_deref().zip(self.tls_key_file.as_deref())
+    }
+
+    pub fn layout_snapshot_file(&self) -> Option<&Path> {
+        self.layout_snapshot_file.as_deref()
+    }
+
+    pub fn signature_directory(&self) -> Option<&Path> {
+        self.signature_directory.as_deref()
+    }
+
+    pub fn default_strictness(&self) -> u32 {
+        self.default_strictness
+    }
+
+    pub fn default_context(&self) -> &str {
+        &self.default_context
+    }
+
+    pub fn schedule_file(&self) -> Option<&Path> {
+        self.schedule_file.as_deref()
+    }
+
+    pub fn actions_file(&self) -> Option<&Path> {
+        self.actions_file.as_deref()
+    }
+
+    pub fn hold_seconds(&self) -> u32 {
+        self.hold_seconds
+    }
+
+    pub fn policy_file(&self) -> Option<&Path> {
+        self.policy_file.as_deref()
+    }
+
+    pub fn kv_file(&self) -> Option<&Path> {
+        self.kv_file.as_deref()
+    }
+
+    pub fn lint_rules(&self) -> &LintRules {
+        &self.lint_rules
+    }
+
+    pub fn twitch_channel(&self) -> Option<&str> {
+        self.twitch_channel.as_deref()
+    }
+
+    pub fn twitch_user(&self) -> Option<&str> {
+        self.twitch_user.as_deref()
+    }
+
+    pub fn twitch_token(&self) -> Option<&str> {
+        self.twitch_token.as_deref()
+    }
+
+    pub fn chat_announcements(&self) -> &BTreeSet<String> {
+        &self.chat_announcements
+    }
+
+    pub fn relay_url(&self) -> Option<&str> {
+        self.relay_url.as_deref()
+    }
+
+    pub fn relay_token(&self) -> Option<&str> {
+        self.relay_token.as_deref()
+    }
+
+    pub fn audit_file(&self) -> Option<&Path> {
+        self.audit_file.as_deref()
+    }
+
+    pub fn audit_max_size(&self) -> u32 {
+        self.audit_max_size
+    }
+
+    pub fn audit_files(&self) -> u32 {
+        self.audit_files
+    }
+
+    pub fn pause_hotkey(&self) -> Option<&str> {
+        self.pause_hotkey.as_deref()
+    }
+
+    pub fn pause_refund(&self) -> bool {
+        self.pause_refund
+    }
+
+    pub fn twitch_client_id(&self) -> Option<&str> {
+        self.twitch_client_id.as_deref()
+    }
+
+    pub fn helix_token(&self) -> Option<&str> {
+        self.helix_token.as_deref().or(self.twitch_token())
+    }
+
+    pub fn helix_base_url(&self) -> &str {
+        &self.helix_base_url
+    }
+
+    fn read_u32(name: &str) -> Option<u32> {
+        std::env::var(environment::variable_name(name)).ok()
+            .and_then(|value| value.parse::<u32>().ok())
+            .or_else(|| unsafe { Self::read_u32_from_registry(name).ok() })
+    }
+
+    fn read_string(name: &str) -> Option<String> {
+        std::env::var(environment::variable_name(name)).ok()
+            .or_else(|| unsafe { Self::read_string_from_registry(name).ok() })
+            .filter(|value| !value.is_empty())
+    }
+
+    // Comma-separated, e.g. "GameOver, SetMaxPlayer".
+    fn read_list(name: &str) -> Option<BTreeSet<String>> {
+        Self::read_string(name).map(|value| value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_owned).collect())
+    }
+
+    unsafe fn open_registry_key() -> Result<HKEY, Box<dyn Error>> {
+        let mut key = MaybeUninit::<HKEY>::uninit();
+        RegOpenKeyExW(HKEY_CURRENT_USER, w!("Software\\LegacyClonk Team\\LCTwitch"), 0, KEY_READ, key.as_mut_ptr()).ok()?;
+        Ok(key.assume_init())
+    }
+
+    unsafe fn read_string_from_registry(name: &str) -> Result<String, Box<dyn Error>> {
+        let key = Self::open_registry_key()?;
+        let name = HSTRING::from(name);
+
+        let value = {
+            let mut value_type = REG_VALUE_TYPE::default();
+            let mut size = 0u32;
+
+            RegQueryValueExW(key, &name, None, Some(&mut value_type as *mut _), None, Some(&mut size as *mut _)).ok()
+                .map_err(|e| e.into())
+                .and_then(|_| if value_type == REG_SZ { Ok(()) } else { Err(Box::<dyn Error>::from("Not a string value")) })
+                .and_then(|_| {
+                    let mut buffer = vec![0u16; (size as usize + 1) / 2];
+                    RegQueryValueExW(key, &name, None, None, Some(buffer.as_mut_ptr() as *mut u8), Some(&mut size as *mut _)).ok()?;
+
+                    buffer.truncate(size as usize / 2);
+                    while buffer.last() == Some(&0) {
+                        buffer.pop();
+                    }
+
+                    Ok(String::from_utf16(&buffer)?)
+                })
         };
 
-        let port = {
-            let mut port = MaybeUninit::<u32>::uninit();
+        RegCloseKey(key);
+
+        value
+    }
+
+    unsafe fn read_u32_from_registry(name: &str) -> Result<u32, Box<dyn Error>> {
+        let key = Self::open_registry_key()?;
+
+        let value = {
+            let mut value = MaybeUninit::<u32>::uninit();
             let mut size = std::mem::size_of::<u32>() as u32;
-            RegQueryValueExW(key, w!("HttpServerPort"), None, None, Some(port.as_mut_ptr() as *mut u8), Some(&mut size as *mut _)).ok().map(|_| port.assume_init())
+            RegQueryValueExW(key, &HSTRING::from(name), None, None, Some(value.as_mut_ptr() as *mut u8), Some(&mut size as *mut _)).ok().map(|_| value.assume_init())
         };
 
         RegCloseKey(key);
 
-        port.map_err(|e| e.into())
-        .and_then(|port| u16::try_from(port).map_err(|e| e.into()))
+        value.map_err(|e| e.into())
     }
 }
 
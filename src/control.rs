@@ -0,0 +1,297 @@
+use std::{error::Error, ffi::{CStr, CString, c_char, c_void}, time::Duration};
+
+use byte_strings::c_str;
+use cpp::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{LCTwitch, http::{self, ErrorCode}, layout::Layout, policy::Origin, script::StdStrBuf, signature::Symbols};
+
+type C4GameControl = c_void;
+type C4ControlPacket = c_void;
+type C4PlayerList = c_void;
+type C4Player = c_void;
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+}
+
+// C4PacketType
+pub(crate) const CID_SCRIPT: i32 = 0x80 | 0x08;
+const CID_PLAYER_CONTROL: i32 = 0x80 | 0x21;
+const CID_MESSAGE: i32 = 0x80 | 0x23;
+
+// C4ControlDeliveryType::CDT_Decide, i.e. the engine picks the delivery type like it does for player input. Either way,
+// the packet is executed on all clients and recorded in the replay.
+const CDT_DECIDE: i32 = 4;
+
+// C4ControlMessageType::C4CMT_Normal
+const C4CMT_NORMAL: i32 = 0;
+
+// Adding this to a COM_* value gives the matching release command, e.g. COM_Left_R.
+const COM_RELEASE: i32 = 16;
+
+// Viewers only get to take over briefly.
+const MAX_DURATION: u64 = 10_000;
+
+// Creates control packets and queues them like the engine does for input.
+#[derive(Clone, Copy)]
+pub(crate) struct ControlQueue {
+    game_control: *mut C4GameControl,
+    constructor: extern "win64" fn(*mut C4ControlPacket),
+    buf_copy: extern "win64" fn(*mut c_void),
+    do_input: extern "win64" fn(*mut C4GameControl, i32, *mut C4ControlPacket, i32)
+}
+
+unsafe impl Send for ControlQueue {}
+unsafe impl Sync for ControlQueue {}
+
+impl ControlQueue {
+    pub(crate) fn new(symbols: &Symbols, layout: &Layout) -> Result<ControlQueue, Box<dyn Error>> {
+        Ok(ControlQueue {
+            game_control: layout.address("Game.Control")?,
+            constructor: symbols.function(c_str!("C4ControlPacket::C4ControlPacket"))?,
+            buf_copy: symbols.function(c_str!("StdStrBuf::Copy"))?,
+            do_input: symbols.function(c_str!("C4GameControl::DoInput"))?
+        })
+    }
+
+    // Returns a zeroed packet with the C4ControlPacket part constructed. The caller sets the vtable.
+    pub(crate) unsafe fn allocate(&self, size: usize) -> Option<*mut C4ControlPacket> {
+        let memory = cpp!(unsafe [size as "std::size_t"] -> *mut c_void as "void *" {
+            return ::operator new(size, std::nothrow);
+        });
+
+        if memory.is_null() {
+            return None;
+        }
+
+        memory.write_bytes(0, size);
+        (self.constructor)(memory);
+        Some(memory)
+    }
+
+    pub(crate) unsafe fn free(&self, packet: *mut C4ControlPacket) {
+        cpp!(unsafe [packet as "void *"] {
+            ::operator delete(packet);
+        });
+    }
+
+    // Fills a StdStrBuf member of a packet.
+    pub(crate) unsafe fn set_string(&self, buf: *mut StdStrBuf, text: &CStr) -> bool {
+        let bytes = text.to_bytes_with_nul();
+
+        let data = malloc(bytes.len()) as *mut c_char;
+        if data.is_null() {
+            return false;
+        }
+
+        data.copy_from_nonoverlapping(bytes.as_ptr() as *const _, bytes.len());
+
+        (*buf).is_ref = false;
+        (*buf).data = data;
+        (*buf).size = bytes.len();
+        (self.buf_copy)(buf as *mut c_void);
+        true
+    }
+
+    // The engine owns the packet from here on.
+    pub(crate) unsafe fn push(&self, packet_type: i32, packet: *mut C4ControlPacket) {
+        (self.do_input)(self.game_control, packet_type, packet, CDT_DECIDE);
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerCommand {
+    Left,
+    Right,
+    Up,
+    Down,
+    Throw,
+    Dig,
+    Special,
+    Special2,
+    // Alias of Up, Clonks jump with the same key.
+    Jump,
+    // Alias of Throw.
+    Use
+}
+
+impl PlayerCommand {
+    // COM_* from C4Constants.h.
+    fn com(self) -> i32 {
+        match self {
+            PlayerCommand::Left => 1,
+            PlayerCommand::Right => 2,
+            PlayerCommand::Up | PlayerCommand::Jump => 3,
+            PlayerCommand::Down => 4,
+            // Clonks use and throw items with the same key.
+            PlayerCommand::Throw | PlayerCommand::Use => 5,
+            PlayerCommand::Dig => 6,
+            PlayerCommand::Special => 7,
+            PlayerCommand::Special2 => 8
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlRequest {
+    // With a duration in milliseconds, the key is held down that long and then released.
+    Command {
+        player: i32,
+        command: PlayerCommand,
+        #[serde(default)]
+        duration: Option<u64>
+    },
+    // Shown in the game chat like a message from the host.
+    Message {
+        text: String
+    }
+}
+
+struct PacketLayout {
+    size: usize,
+    vtable: *const *const c_void
+}
+
+impl PacketLayout {
+    fn new(symbols: &Symbols, layout: &Layout, type_name: &str) -> Result<PacketLayout, Box<dyn Error>> {
+        let vtable = match layout.vtable(type_name) {
+            Ok(vtable) => vtable,
+            Err(_) => symbols.function(&CString::new(format!("{}::`vftable'", type_name))?)?
+        };
+
+        Ok(PacketLayout {
+            size: layout.type_size(type_name)?,
+            vtable
+        })
+    }
+}
+
+// Sends packets other than scripts, using the engine's own implementation of them.
+pub struct Control {
+    queue: ControlQueue,
+    players: *mut C4PlayerList,
+    get_player: extern "win64" fn(*mut C4PlayerList, i32) -> *mut C4Player,
+
+    player_control: PacketLayout,
+    player_control_player: usize,
+    player_control_command: usize,
+    player_control_data: usize,
+
+    message: PacketLayout,
+    message_type: usize,
+    message_player: usize,
+    message_to_player: usize,
+    message_text: usize
+}
+
+unsafe impl Send for Control {}
+unsafe impl Sync for Control {}
+
+impl Control {
+    pub fn new(symbols: &Symbols, layout: &Layout) -> Result<Control, Box<dyn Error>> {
+        Ok(Control {
+            queue: ControlQueue::new(symbols, layout)?,
+            players: layout.address("Game.Players")?,
+            get_player: symbols.function(c_str!("C4PlayerList::Get"))?,
+
+            player_control: PacketLayout::new(symbols, layout, "C4ControlPlayerControl")?,
+            player_control_player: layout.member_offset("C4ControlPlayerControl", "iPlr")?,
+            player_control_command: layout.member_offset("C4ControlPlayerControl", "iCom")?,
+            player_control_data: layout.member_offset("C4ControlPlayerControl", "iData")?,
+
+            message: PacketLayout::new(symbols, layout, "C4ControlMessage")?,
+            message_type: layout.member_offset("C4ControlMessage", "eType")?,
+            message_player: layout.member_offset("C4ControlMessage", "iPlayer")?,
+            message_to_player: layout.member_offset("C4ControlMessage", "iToPlayer")?,
+            message_text: layout.member_offset("C4ControlMessage", "Message")?
+        })
+    }
+
+    unsafe fn create(&self, packet: &PacketLayout) -> Option<*mut C4ControlPacket> {
+        let memory = self.queue.allocate(packet.size)?;
+        (memory as *mut *const *const c_void).write(packet.vtable);
+        Some(memory)
+    }
+
+    unsafe fn push_player_control(&self, player: i32, command: i32) -> Result<(), http::Error> {
+        if (self.get_player)(self.players, player).is_null() {
+            return Err(ErrorCode::PlayerNotFound.into());
+        }
+
+        let memory = self.create(&self.player_control).ok_or_else(|| http::Error::from("Out of memory".to_owned()))?;
+        (memory.add(self.player_control_player) as *mut i32).write(player);
+        (memory.add(self.player_control_command) as *mut i32).write(command);
+        (memory.add(self.player_control_data) as *mut i32).write(0);
+
+        self.queue.push(CID_PLAYER_CONTROL, memory);
+        Ok(())
+    }
+
+    unsafe fn push_message(&self, text: &CStr) -> Result<(), http::Error> {
+        let memory = self.create(&self.message).ok_or_else(|| http::Error::from("Out of memory".to_owned()))?;
+        (memory.add(self.message_type) as *mut i32).write(C4CMT_NORMAL);
+        (memory.add(self.message_player) as *mut i32).write(-1);
+        (memory.add(self.message_to_player) as *mut i32).write(-1);
+
+        if !self.queue.set_string(memory.add(self.message_text) as *mut StdStrBuf, text) {
+            self.queue.free(memory);
+            return Err(http::Error::from("Out of memory".to_owned()));
+        }
+
+        self.queue.push(CID_MESSAGE, memory);
+        Ok(())
+    }
+
+    // Packets can only be created in the main thread.
+    async fn in_main_thread(&self, instance: &LCTwitch, op: impl FnOnce(&Control) -> Result<(), http::Error> + Send) -> Result<(), http::Error> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        instance.run_in_main_thread(move || {
+            let _ = tx.send(op(self));
+        });
+
+        rx.await.map_err(|e| http::Error::from(e.to_string()))?
+    }
+
+    pub async fn send(&self, instance: &LCTwitch, request: &ControlRequest, origin: &Origin) -> Result<(), http::Error> {
+        match request {
+            ControlRequest::Command { player, command, duration } => {
+                let (player, command, duration) = (*player, command.com(), *duration);
+                if duration.map_or(false, |duration| duration > MAX_DURATION) {
+                    return Err(http::Error {
+                        code: ErrorCode::InvalidArguments,
+                        message: format!("duration must not be longer than {} ms", MAX_DURATION)
+                    });
+                }
+
+                instance.script()?.authorize(instance, origin)?;
+                self.in_main_thread(instance, move |control| unsafe { control.push_player_control(player, command) }).await?;
+
+                if let Some(duration) = duration {
+                    tokio::time::sleep(Duration::from_millis(duration)).await;
+
+                    // The player may have left in the meantime, in which case there is nothing to release.
+                    let _ = self.in_main_thread(instance, move |control| unsafe { control.push_player_control(player, command + COM_RELEASE) }).await;
+                }
+
+                Ok(())
+            },
+            ControlRequest::Message { text } => {
+                if text.trim().is_empty() || text.chars().any(char::is_control) {
+                    return Err(http::Error {
+                        code: ErrorCode::InvalidArguments,
+                        message: "text must not be empty or contain control characters".to_owned()
+                    });
+                }
+
+                let text = CString::new(text.as_str()).map_err(|_| ErrorCode::InvalidArguments)?;
+
+                instance.script()?.authorize(instance, origin)?;
+                self.in_main_thread(instance, move |control| unsafe { control.push_message(&text) }).await
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -1,5 +1,7 @@
 use std::error::Error;
 use std::ffi::{c_char, c_void, CStr, CString};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicPtr, Ordering};
 use windows::Win32::System::LibraryLoader::GetModuleFileNameA;
 use windows::Win32::System::Threading::GetCurrentThread;
 use windows::Win32::Foundation::{HANDLE, MAX_PATH, HINSTANCE, NO_ERROR, WIN32_ERROR};
@@ -11,8 +13,8 @@ extern "system" {
     fn DetourTransactionAbort() -> u32;
     fn DetourTransactionCommit() -> u32;
     fn DetourUpdateThread(thread: HANDLE) -> u32;
-    fn DetourAttach(pointer: *mut *const c_void, detour: *mut *const c_void) -> u32;
-    fn DetourDetach(pointer: *mut *const c_void, detour: *mut *const c_void) -> u32;
+    fn DetourAttach(pointer: *mut *const c_void, detour: *const c_void) -> u32;
+    fn DetourDetach(pointer: *mut *const c_void, detour: *const c_void) -> u32;
     fn DetourFindFunction(module: *const c_char, function: *const c_char) -> *const c_void;
 }
 
@@ -82,38 +84,51 @@ fn with_transaction<F: FnOnce() -> Result<(), windows::core::Error>>(op: F) -> R
 }
 
 pub struct Detour<T, U> {
-    source: *const T,
-    target: *const U
+    trampoline: &'static AtomicPtr<c_void>,
+    target: *const c_void,
+    _marker: PhantomData<(T, U)>
 }
 
 impl<T, U> Detour<T, U> {
-    pub fn new(source: T, target: U) -> Result<Detour<T, U>, windows::core::Error> where T: Sized, U: Sized {
+    // The trampoline is stored in a static so that the detour function can call the original
+    // function as soon as the transaction is committed.
+    pub fn new(source: T, target: U, trampoline: &'static AtomicPtr<c_void>) -> Result<Detour<T, U>, windows::core::Error> where T: Sized, U: Sized {
+        assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<*const c_void>());
+        assert_eq!(std::mem::size_of::<U>(), std::mem::size_of::<*const c_void>());
+
         unsafe {
-            let mut source = &source as *const T;
-            let mut target = &target as *const U;
+            trampoline.store(std::mem::transmute_copy(&source), Ordering::SeqCst);
+            let target = std::mem::transmute_copy::<U, *const c_void>(&target);
 
             with_transaction(|| {
-                check_result(DetourAttach(&mut source as *mut *const T as *mut *const c_void, &mut target as *mut *const U as *mut *const c_void))
+                check_result(DetourAttach(trampoline as *const AtomicPtr<c_void> as *mut *const c_void, target))
             })
-            .map(|_| Detour { source, target })
+            .map(|_| Detour { trampoline, target, _marker: PhantomData })
         }
     }
 
-    pub unsafe fn source(&self) -> *const T {
-        self.source
+    pub fn source(&self) -> T {
+        unsafe { original(self.trampoline) }
     }
 
-    pub unsafe fn target(&self) -> *const U {
-        self.target
+    pub fn target(&self) -> U {
+        unsafe { std::mem::transmute_copy(&self.target) }
     }
 }
 
+pub unsafe fn original<T>(trampoline: &AtomicPtr<c_void>) -> T {
+    std::mem::transmute_copy(&trampoline.load(Ordering::SeqCst))
+}
+
 impl<T, U> Drop for Detour<T, U> {
     fn drop(&mut self) {
         let _ = with_transaction(|| {
             unsafe {
-                check_result(DetourDetach(&mut self.source as *mut *const T as *mut *const c_void, &mut self.target as *mut *const U as *mut *const c_void))
+                check_result(DetourDetach(self.trampoline as *const AtomicPtr<c_void> as *mut *const c_void, self.target))
             }
         });
     }
-}
\ No newline at end of file
+}
+
+unsafe impl<T, U> Send for Detour<T, U> {}
+unsafe impl<T, U> Sync for Detour<T, U> {}
\ No newline at end of file
@@ -0,0 +1,15 @@
+// Settings passed through the environment (e.g. by lctwitch-launch) take precedence over the registry.
+// Shared with lctwitch-launch, which cannot link against the library.
+pub fn variable_name(name: &str) -> String {
+    let mut result = String::from("LCTWITCH");
+
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            result.push('_');
+        }
+
+        result.push(c.to_ascii_uppercase());
+    }
+
+    result
+}
\ No newline at end of file
@@ -0,0 +1,209 @@
+use std::{collections::VecDeque, sync::{Arc, Mutex}};
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast::{self, error::RecvError};
+use warp::ws::{Message, WebSocket};
+
+use crate::{LCTwitch, approval::PendingApproval, automation::AutomationSource, http::ScriptResult, policy::{Decision, Origin}};
+
+#[derive(Clone, Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    ScheduledScript {
+        id: u64,
+        #[serde(flatten)]
+        result: ScriptResult
+    },
+    Automation {
+        source: AutomationSource,
+        action: String,
+        #[serde(flatten)]
+        result: ScriptResult
+    },
+    HeldResult {
+        id: u64,
+        reference: Option<String>,
+        #[serde(flatten)]
+        result: ScriptResult
+    },
+    // Sent for items that were never delivered, so that clients can refund them.
+    HeldExpired {
+        id: u64,
+        reference: Option<String>
+    },
+    // refund is false if the item was cancelled by a pause that should not refund anything.
+    HeldCancelled {
+        id: u64,
+        reference: Option<String>,
+        refund: bool
+    },
+    PolicyDecision {
+        origin: Origin,
+        #[serde(flatten)]
+        decision: Decision
+    },
+    // LCTwitch_Emit(name, data) from a scenario script.
+    ScenarioEvent {
+        name: String,
+        data: Value
+    },
+    // LCTwitch_Say(message) from a scenario script.
+    ScenarioMessage {
+        message: String
+    },
+    ChatMessage {
+        user: String,
+        message: String
+    },
+    // A value of null means the key was removed.
+    ValueChanged {
+        key: String,
+        value: Value
+    },
+    // Player numbers are the ones scripts use, names are only sent if the build has them.
+    PlayerJoined {
+        player: i32,
+        name: Option<String>
+    },
+    PlayerLeft {
+        player: i32,
+        name: Option<String>
+    },
+    PlayerEliminated {
+        player: i32,
+        name: Option<String>
+    },
+    CrewDied {
+        player: i32,
+        object: i32
+    },
+    RoundStarted,
+    // game_over is false for rounds that were aborted.
+    RoundEnded {
+        game_over: bool
+    },
+    ScenarioLoaded,
+    ScenarioUnloaded,
+    ApprovalRequested {
+        #[serde(flatten)]
+        item: PendingApproval
+    },
+    // by is the moderator's token name. Clients refund the redemption behind the item if refund is set.
+    ApprovalRejected {
+        id: u64,
+        reference: Option<String>,
+        by: Option<String>,
+        reason: Option<String>,
+        refund: bool
+    },
+    ApprovalResult {
+        id: u64,
+        reference: Option<String>,
+        by: Option<String>,
+        #[serde(flatten)]
+        result: ScriptResult
+    },
+    // The kill switch was triggered, by a token name, a chat moderator or "hotkey".
+    Paused {
+        by: Option<String>,
+        reason: Option<String>,
+        refund: bool
+    },
+    Resumed {
+        by: Option<String>
+    },
+    // Published while a replay is played back, when it reaches the frame a script was run in.
+    ReplayAnnotation {
+        frame: i32,
+        origin: Origin,
+        script: String
+    },
+    // A channel point redemption was marked as fulfilled or refunded. error is set if Helix refused it for good.
+    RedemptionUpdated {
+        id: String,
+        reward: String,
+        fulfilled: bool,
+        error: Option<String>
+    }
+}
+
+impl Event {
+    // The type field of the serialized event, e.g. "player_joined".
+    pub fn name(&self) -> String {
+        serde_json::to_value(self).ok()
+            .and_then(|value| value.get("type").and_then(Value::as_str).map(str::to_owned))
+            .unwrap_or_default()
+    }
+}
+
+// How many past events clients can fetch without keeping a WebSocket open.
+const RECENT_EVENTS: usize = 100;
+
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    recent: Mutex<VecDeque<Event>>
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus {
+            sender: broadcast::channel(256).0,
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_EVENTS))
+        }
+    }
+
+    // Events are dropped if nobody is listening.
+    pub fn publish(&self, event: Event) {
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == RECENT_EVENTS {
+                recent.pop_front();
+            }
+
+            recent.push_back(event.clone());
+        }
+
+        let _ = self.sender.send(event);
+    }
+
+    pub fn recent(&self) -> Vec<Event> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+pub async fn stream(socket: WebSocket, instance: Arc<LCTwitch>) {
+    let mut events = instance.events().subscribe();
+    let (mut sender, mut receiver) = socket.split();
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let message = match event {
+                    Ok(event) => match serde_json::to_string(&event) {
+                        Ok(message) => message,
+                        Err(_) => continue
+                    },
+                    // A client that cannot keep up misses events instead of stalling everyone else.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break
+                };
+
+                if sender.send(Message::text(message)).await.is_err() {
+                    break;
+                }
+            },
+            message = receiver.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {},
+                _ => break
+            }
+        }
+    }
+
+    let _ = sender.close().await;
+}
\ No newline at end of file
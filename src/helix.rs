@@ -0,0 +1,181 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+use serde_json::{Value, json};
+use tokio::sync::{OnceCell, mpsc::{self, UnboundedReceiver, UnboundedSender}};
+
+use crate::{LCTwitch, events::Event, policy::Origin};
+
+const MAX_ATTEMPTS: u32 = 5;
+
+struct Redemption {
+    id: String,
+    reward: String,
+    fulfilled: bool
+}
+
+// Whether a failed call is worth retrying.
+enum HelixError {
+    Transient(String),
+    Permanent(String)
+}
+
+impl From<reqwest::Error> for HelixError {
+    fn from(value: reqwest::Error) -> Self {
+        HelixError::Transient(value.to_string())
+    }
+}
+
+impl std::fmt::Display for HelixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transient(message) | Self::Permanent(message) => message.fmt(f)
+        }
+    }
+}
+
+// Marks channel point redemptions as fulfilled or refunds them, depending on how the request they came with
+// ended. Only requests that carry both the redemption id as reference and the reward id are considered.
+pub struct Helix {
+    redemptions: UnboundedSender<Redemption>,
+    receiver: Mutex<Option<UnboundedReceiver<Redemption>>>,
+    broadcaster_id: OnceCell<String>
+}
+
+impl Helix {
+    pub fn new() -> Helix {
+        let (redemptions, receiver) = mpsc::unbounded_channel();
+
+        Helix {
+            redemptions,
+            receiver: Mutex::new(Some(receiver)),
+            broadcaster_id: OnceCell::new()
+        }
+    }
+
+    // Does nothing if Helix is not configured.
+    pub fn resolve(&self, origin: &Origin, fulfilled: bool) {
+        if let (Some(id), Some(reward)) = (&origin.reference, &origin.reward) {
+            let _ = self.redemptions.send(Redemption {
+                id: id.clone(),
+                reward: reward.clone(),
+                fulfilled
+            });
+        }
+    }
+}
+
+struct HelixClient {
+    client: reqwest::Client,
+    base_url: String,
+    client_id: String,
+    token: String,
+    channel: String
+}
+
+impl HelixClient {
+    fn check_status(response: &reqwest::Response) -> Result<(), HelixError> {
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        }
+        else if status.is_server_error() || status.as_u16() == 429 {
+            Err(HelixError::Transient(format!("Helix returned {}", status)))
+        }
+        else {
+            Err(HelixError::Permanent(format!("Helix returned {}", status)))
+        }
+    }
+
+    async fn broadcaster_id(&self) -> Result<String, HelixError> {
+        let response = self.client.get(format!("{}/users", self.base_url))
+            .query(&[("login", &self.channel)])
+            .header("Client-Id", &self.client_id)
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        Self::check_status(&response)?;
+
+        response.json::<Value>().await?
+            .pointer("/data/0/id")
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+            .ok_or_else(|| HelixError::Permanent(format!("Unknown channel {}", self.channel)))
+    }
+
+    async fn update(&self, broadcaster_id: &str, redemption: &Redemption) -> Result<(), HelixError> {
+        let response = self.client.patch(format!("{}/channel_points/custom_rewards/redemptions", self.base_url))
+            .query(&[("id", redemption.id.as_str()), ("broadcaster_id", broadcaster_id), ("reward_id", redemption.reward.as_str())])
+            .header("Client-Id", &self.client_id)
+            .bearer_auth(&self.token)
+            .json(&json!({ "status": if redemption.fulfilled { "FULFILLED" } else { "CANCELED" } }))
+            .send()
+            .await?;
+
+        Self::check_status(&response)
+    }
+}
+
+async fn resolve(instance: Arc<LCTwitch>, client: Arc<HelixClient>, redemption: Redemption) {
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 1;
+
+    let result = loop {
+        let result = async {
+            let broadcaster_id = instance.helix().broadcaster_id.get_or_try_init(|| client.broadcaster_id()).await?;
+            client.update(broadcaster_id, &redemption).await
+        }.await;
+
+        match result {
+            Err(HelixError::Transient(_)) if attempt < MAX_ATTEMPTS => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            },
+            result => break result
+        }
+    };
+
+    if let Err(e) = &result {
+        instance.log_in_main_thread(format!("LCTwitch: Could not update redemption {}: {}", redemption.id, e));
+    }
+
+    instance.events().publish(Event::RedemptionUpdated {
+        id: redemption.id,
+        reward: redemption.reward,
+        fulfilled: redemption.fulfilled,
+        error: result.err().map(|e| e.to_string())
+    });
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let mut redemptions = match instance.helix().receiver.lock().unwrap().take() {
+        Some(redemptions) => redemptions,
+        None => return
+    };
+
+    let config = instance.config();
+
+    // Dropping the receiver makes resolve() discard everything.
+    let client = match (config.twitch_client_id(), config.helix_token(), config.twitch_channel()) {
+        (Some(client_id), Some(token), Some(channel)) => match reqwest::Client::builder().timeout(Duration::from_secs(10)).build() {
+            Ok(client) => Arc::new(HelixClient {
+                client,
+                base_url: config.helix_base_url().trim_end_matches('/').to_owned(),
+                client_id: client_id.to_owned(),
+                token: token.trim_start_matches("oauth:").to_owned(),
+                channel: channel.trim_start_matches('#').to_lowercase()
+            }),
+            Err(e) => {
+                instance.log_in_main_thread(format!("LCTwitch: Redemptions are not updated: {}", e));
+                return;
+            }
+        },
+        _ => return
+    };
+
+    // Each redemption is retried on its own, so that one failing does not hold up the others.
+    while let Some(redemption) = redemptions.recv().await {
+        tokio::spawn(resolve(instance.clone(), client.clone(), redemption));
+    }
+}
\ No newline at end of file
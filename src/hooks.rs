@@ -0,0 +1,264 @@
+use std::{collections::BTreeSet, error::Error, ffi::{c_char, c_void}, sync::{Arc, Mutex, atomic::{AtomicPtr, Ordering}}, time::Duration};
+
+use byte_strings::c_str;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::{LCTwitch, detour::{self, Detour}, events::Event, layout::Layout, resolver::Field, script::StdStrBuf, signature::Symbols};
+
+type C4Game = c_void;
+type C4PlayerList = c_void;
+type C4Player = c_void;
+type C4PlayerInfo = c_void;
+type C4Object = c_void;
+
+type FnPlayerListJoin = extern "win64" fn(*mut C4PlayerList, *const c_char, bool, i32, *const c_char, *mut C4PlayerInfo) -> *mut C4Player;
+type FnPlayerClear = extern "win64" fn(*mut C4Player);
+type FnPlayerEliminate = extern "win64" fn(*mut C4Player);
+type FnObjectAssignDeath = extern "win64" fn(*mut C4Object, bool);
+type FnGameInit = extern "win64" fn(*mut C4Game) -> bool;
+type FnGameClear = extern "win64" fn(*mut C4Game);
+type FnGameDoGameOver = extern "win64" fn(*mut C4Game) -> bool;
+
+static PLAYER_LIST_JOIN: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static PLAYER_CLEAR: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static PLAYER_ELIMINATE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static OBJECT_ASSIGN_DEATH: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static GAME_INIT: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static GAME_CLEAR: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static GAME_DO_GAME_OVER: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+static STATE: AtomicPtr<HookState> = AtomicPtr::new(std::ptr::null_mut());
+
+struct HookState {
+    player_number: Field<i32>,
+    // Optional, since only the number is needed to tell players apart.
+    player_name: Option<Field<StdStrBuf>>,
+    object_owner: Field<i32>,
+    object_number: Field<i32>,
+    object_info: Field<*mut c_void>,
+    // C4Player::Clear also runs for players that never fully joined, and again when they are destroyed.
+    joined: Mutex<BTreeSet<i32>>,
+    events: UnboundedSender<Event>
+}
+
+impl HookState {
+    unsafe fn player(&self, player: *mut C4Player) -> Option<(i32, Option<String>)> {
+        if player.is_null() {
+            return None;
+        }
+
+        let number = self.player_number.read(player)?;
+        let name = self.player_name.as_ref()
+            .and_then(|name| name.ptr(player))
+            .map(|name| (*name).to_string_lossy());
+
+        Some((number, name))
+    }
+
+    fn publish(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+}
+
+// Hooks into the engine to report what happens in the game. Round starts and ends are derived in run(), since the
+// engine sets IsRunning in several places.
+pub struct GameHooks {
+    _player_list_join: Detour<FnPlayerListJoin, FnPlayerListJoin>,
+    _player_clear: Detour<FnPlayerClear, FnPlayerClear>,
+    _player_eliminate: Detour<FnPlayerEliminate, FnPlayerEliminate>,
+    _object_assign_death: Detour<FnObjectAssignDeath, FnObjectAssignDeath>,
+    _game_init: Detour<FnGameInit, FnGameInit>,
+    _game_clear: Detour<FnGameClear, FnGameClear>,
+    _game_do_game_over: Detour<FnGameDoGameOver, FnGameDoGameOver>,
+    _state: Box<HookState>,
+    events: Mutex<Option<UnboundedReceiver<Event>>>
+}
+
+unsafe impl Send for GameHooks {}
+unsafe impl Sync for GameHooks {}
+
+impl GameHooks {
+    pub fn new(symbols: &Symbols, layout: &Layout) -> Result<GameHooks, Box<dyn Error>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let state = Box::new(HookState {
+            player_number: layout.resolve_field::<i32>("C4Player", "Number")?,
+            player_name: layout.resolve_field::<StdStrBuf>("C4Player", "Name").ok(),
+            object_owner: layout.resolve_field::<i32>("C4Object", "Owner")?,
+            object_number: layout.resolve_field::<i32>("C4Object", "Number")?,
+            object_info: layout.resolve_field::<*mut c_void>("C4Object", "Info")?,
+            joined: Mutex::new(BTreeSet::new()),
+            events: sender
+        });
+
+        // Resolve everything before hooking anything, so that a missing function does not leave half of the hooks in place.
+        let player_list_join = symbols.function::<FnPlayerListJoin>(c_str!("C4PlayerList::Join"))?;
+        let player_clear = symbols.function::<FnPlayerClear>(c_str!("C4Player::Clear"))?;
+        let player_eliminate = symbols.function::<FnPlayerEliminate>(c_str!("C4Player::Eliminate"))?;
+        let object_assign_death = symbols.function::<FnObjectAssignDeath>(c_str!("C4Object::AssignDeath"))?;
+        let game_init = symbols.function::<FnGameInit>(c_str!("C4Game::Init"))?;
+        let game_clear = symbols.function::<FnGameClear>(c_str!("C4Game::Clear"))?;
+        let game_do_game_over = symbols.function::<FnGameDoGameOver>(c_str!("C4Game::DoGameOver"))?;
+
+        let hooks = GameHooks {
+            _player_list_join: Detour::new(player_list_join, player_list_join_hook as FnPlayerListJoin, &PLAYER_LIST_JOIN)?,
+            _player_clear: Detour::new(player_clear, player_clear_hook as FnPlayerClear, &PLAYER_CLEAR)?,
+            _player_eliminate: Detour::new(player_eliminate, player_eliminate_hook as FnPlayerEliminate, &PLAYER_ELIMINATE)?,
+            _object_assign_death: Detour::new(object_assign_death, object_assign_death_hook as FnObjectAssignDeath, &OBJECT_ASSIGN_DEATH)?,
+            _game_init: Detour::new(game_init, game_init_hook as FnGameInit, &GAME_INIT)?,
+            _game_clear: Detour::new(game_clear, game_clear_hook as FnGameClear, &GAME_CLEAR)?,
+            _game_do_game_over: Detour::new(game_do_game_over, game_do_game_over_hook as FnGameDoGameOver, &GAME_DO_GAME_OVER)?,
+            _state: state,
+            events: Mutex::new(Some(receiver))
+        };
+
+        // Until then, the hooks only call the original functions.
+        STATE.store(hooks._state.as_ref() as *const HookState as *mut HookState, Ordering::SeqCst);
+        Ok(hooks)
+    }
+}
+
+impl Drop for GameHooks {
+    fn drop(&mut self) {
+        STATE.store(std::ptr::null_mut(), Ordering::SeqCst);
+    }
+}
+
+fn state() -> Option<&'static HookState> {
+    unsafe { STATE.load(Ordering::SeqCst).as_ref() }
+}
+
+extern "win64" fn player_list_join_hook(players: *mut C4PlayerList, filename: *const c_char, scenario_init: bool, at_client: i32, at_client_name: *const c_char, info: *mut C4PlayerInfo) -> *mut C4Player {
+    unsafe {
+        let player = detour::original::<FnPlayerListJoin>(&PLAYER_LIST_JOIN)(players, filename, scenario_init, at_client, at_client_name, info);
+
+        if let Some(state) = state() {
+            if let Some((number, name)) = state.player(player) {
+                state.joined.lock().unwrap().insert(number);
+                state.publish(Event::PlayerJoined { player: number, name });
+            }
+        }
+
+        player
+    }
+}
+
+extern "win64" fn player_clear_hook(player: *mut C4Player) {
+    unsafe {
+        if let Some(state) = state() {
+            if let Some((number, name)) = state.player(player) {
+                if state.joined.lock().unwrap().remove(&number) {
+                    state.publish(Event::PlayerLeft { player: number, name });
+                }
+            }
+        }
+
+        detour::original::<FnPlayerClear>(&PLAYER_CLEAR)(player);
+    }
+}
+
+extern "win64" fn player_eliminate_hook(player: *mut C4Player) {
+    unsafe {
+        if let Some(state) = state() {
+            if let Some((number, name)) = state.player(player) {
+                state.publish(Event::PlayerEliminated { player: number, name });
+            }
+        }
+
+        detour::original::<FnPlayerEliminate>(&PLAYER_ELIMINATE)(player);
+    }
+}
+
+extern "win64" fn object_assign_death_hook(object: *mut C4Object, forced: bool) {
+    unsafe {
+        // Only crew members have an info section.
+        if let Some(state) = state() {
+            if state.object_info.read(object).map_or(false, |info| !info.is_null()) {
+                if let (Some(player), Some(number)) = (state.object_owner.read(object), state.object_number.read(object)) {
+                    state.publish(Event::CrewDied { player, object: number });
+                }
+            }
+        }
+
+        detour::original::<FnObjectAssignDeath>(&OBJECT_ASSIGN_DEATH)(object, forced);
+    }
+}
+
+extern "win64" fn game_init_hook(game: *mut C4Game) -> bool {
+    unsafe {
+        let result = detour::original::<FnGameInit>(&GAME_INIT)(game);
+
+        if let Some(state) = state().filter(|_| result) {
+            state.publish(Event::ScenarioLoaded);
+        }
+
+        result
+    }
+}
+
+extern "win64" fn game_clear_hook(game: *mut C4Game) {
+    unsafe {
+        detour::original::<FnGameClear>(&GAME_CLEAR)(game);
+
+        if let Some(state) = state() {
+            state.joined.lock().unwrap().clear();
+            state.publish(Event::ScenarioUnloaded);
+        }
+    }
+}
+
+extern "win64" fn game_do_game_over_hook(game: *mut C4Game) -> bool {
+    unsafe {
+        // Returns false if the game was already over.
+        let result = detour::original::<FnGameDoGameOver>(&GAME_DO_GAME_OVER)(game);
+
+        if let Some(state) = state().filter(|_| result) {
+            state.publish(Event::RoundEnded { game_over: true });
+        }
+
+        result
+    }
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let mut hook_events = instance.game_hooks().ok().and_then(|hooks| hooks.events.lock().unwrap().take());
+    let mut interval = tokio::time::interval(Duration::from_millis(25));
+
+    let mut was_running = false;
+    // Whether round_ended is still to be sent for the current round.
+    let mut in_round = false;
+
+    loop {
+        interval.tick().await;
+
+        if let Some(receiver) = &mut hook_events {
+            while let Ok(event) = receiver.try_recv() {
+                match event {
+                    Event::RoundEnded { .. } if !in_round => continue,
+                    Event::RoundEnded { .. } => in_round = false,
+                    // Games that are cleared while running were aborted.
+                    Event::ScenarioUnloaded if in_round => {
+                        in_round = false;
+                        instance.events().publish(Event::RoundEnded { game_over: false });
+                    },
+                    _ => {}
+                }
+
+                instance.events().publish(event);
+            }
+        }
+
+        // Also the only source of round events if the hooks are not available.
+        let running = instance.script().map_or(false, |script| script.is_running());
+        if running && !was_running {
+            in_round = true;
+            instance.events().publish(Event::RoundStarted);
+        }
+        else if !running && was_running && in_round {
+            in_round = false;
+            instance.events().publish(Event::RoundEnded { game_over: false });
+        }
+
+        was_running = running;
+    }
+}
\ No newline at end of file
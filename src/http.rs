@@ -2,15 +2,74 @@ use std::convert::Infallible;
 use std::sync::{Arc};
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use serde_repr::*;
 use tokio::sync::oneshot::Receiver;
 use warp::{self, hyper::StatusCode, reject, reply, Reply, Filter, Rejection};
 
-use crate::LCTwitch;
+use crate::{LCTwitch, actions, args, audit::{AuditEntry, AuditQuery}, automation::PauseTarget, control::ControlRequest, events, killswitch, relay, policy::{Origin, Source}, queue::{HeldItem, HeldRequest}, scheduler::{self, Trigger}, script::C4AulScriptStrict};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptTarget {
+    Object(i32),
+    CursorOfPlayer(i32)
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct ScriptOptions {
+    #[serde(default)]
+    pub target: Option<ScriptTarget>,
+    #[serde(default)]
+    pub strictness: Option<u32>,
+    #[serde(default)]
+    pub context: Option<String>
+}
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Script {
-    pub script: String
+    pub script: String,
+    #[serde(flatten)]
+    pub options: ScriptOptions,
+    #[serde(default)]
+    pub args: Map<String, Value>
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct HoldOptions {
+    // Seconds to hold the request for if no scenario is running, overriding the HoldSeconds setting. 0 disables holding.
+    #[serde(default)]
+    pub hold: Option<u64>,
+    #[serde(default)]
+    pub reference: Option<String>,
+    // With a redemption id as reference, the redemption is fulfilled or refunded depending on the result. Only honoured
+    // for the redemption tokens of the policy.
+    #[serde(default)]
+    pub reward: Option<String>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ScriptRequest {
+    #[serde(flatten)]
+    pub script: Script,
+    #[serde(flatten)]
+    pub hold: HoldOptions
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BatchScript {
+    pub script: String,
+    #[serde(default)]
+    pub args: Map<String, Value>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Batch {
+    pub scripts: Vec<BatchScript>,
+    #[serde(flatten)]
+    pub options: ScriptOptions,
+    #[serde(default)]
+    pub stop_on_error: bool
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize, Debug)]
@@ -48,11 +107,75 @@ impl std::fmt::Display for Error {
     }
 }
 
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct ActionRequest {
+    #[serde(default)]
+    pub args: Map<String, Value>,
+    #[serde(flatten)]
+    pub hold: HoldOptions
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug)]
+pub struct PauseQuery {
+    #[serde(default)]
+    pub target: PauseTarget
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct ApprovalEdit {
+    #[serde(default)]
+    pub args: Map<String, Value>
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct RejectQuery {
+    #[serde(default)]
+    pub reason: Option<String>
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct KillSwitchQuery {
+    #[serde(default)]
+    pub reason: Option<String>,
+    // Defaults to the PauseRefund setting.
+    #[serde(default)]
+    pub refund: Option<bool>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ScheduleRequest {
+    #[serde(flatten)]
+    pub script: Script,
+    pub when: Trigger
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ScriptReply {
     pub result: String
 }
 
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ScriptResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>
+}
+
+impl From<Result<String, Error>> for ScriptResult {
+    fn from(value: Result<String, Error>) -> Self {
+        match value {
+            Ok(result) => ScriptResult { result: Some(result), error: None },
+            Err(error) => ScriptResult { result: None, error: Some(error) }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BatchReply {
+    pub results: Vec<ScriptResult>
+}
+
 
 #[repr(u8)]
 #[derive(Clone, Copy, Serialize_repr, Deserialize_repr, Debug)]
@@ -63,7 +186,31 @@ pub enum ErrorCode {
     NoScriptingInReplays,
     LeagueActive,
     ScriptParseError,
-    InternalServerError
+    InternalServerError,
+    IncompatibleBuild,
+    ObjectNotFound,
+    InvalidStrictness,
+    InvalidContext,
+    InvalidArguments,
+    ScriptExecutionError,
+    NotExecuted,
+    EmptyBatch,
+    ScheduleEntryNotFound,
+    UnknownAction,
+    HeldItemNotFound,
+    PolicyDenied,
+    InvalidToken,
+    ScriptRejected,
+    KeyNotFound,
+    InvalidValue,
+    ReadOnlyKey,
+    PlayerNotFound,
+    RelayFailed,
+    Paused,
+    ApprovalNotFound,
+    Unsynchronized,
+    TokenRequired,
+    NotModerator
 }
 
 impl std::fmt::Display for ErrorCode {
@@ -75,7 +222,31 @@ impl std::fmt::Display for ErrorCode {
             Self::NoScriptingInReplays => write!(f, "Scripting in replays is disabled"),
             Self::LeagueActive => write!(f, "Scripting in league games is not allowed"),
             Self::ScriptParseError => write!(f, "Parse error"),
-            Self::InternalServerError => write!(f, "Internal server error")
+            Self::InternalServerError => write!(f, "Internal server error"),
+            Self::IncompatibleBuild => write!(f, "Scripting is disabled for this game build"),
+            Self::ObjectNotFound => write!(f, "Target object does not exist"),
+            Self::InvalidStrictness => write!(f, "Strictness must be between 0 and 3"),
+            Self::InvalidContext => write!(f, "Context must not contain null characters"),
+            Self::InvalidArguments => write!(f, "Invalid script arguments"),
+            Self::ScriptExecutionError => write!(f, "Script error"),
+            Self::NotExecuted => write!(f, "Not executed because an earlier script failed"),
+            Self::EmptyBatch => write!(f, "Batch contains no scripts"),
+            Self::ScheduleEntryNotFound => write!(f, "Schedule entry not found"),
+            Self::UnknownAction => write!(f, "Unknown action"),
+            Self::HeldItemNotFound => write!(f, "Held item not found"),
+            Self::PolicyDenied => write!(f, "Denied by policy"),
+            Self::InvalidToken => write!(f, "Invalid token"),
+            Self::ScriptRejected => write!(f, "Script rejected"),
+            Self::KeyNotFound => write!(f, "Key not found"),
+            Self::InvalidValue => write!(f, "Value cannot be represented in C4Script"),
+            Self::ReadOnlyKey => write!(f, "Key is read-only"),
+            Self::PlayerNotFound => write!(f, "Player does not exist"),
+            Self::RelayFailed => write!(f, "Could not reach the host"),
+            Self::Paused => write!(f, "LCTwitch has been paused"),
+            Self::ApprovalNotFound => write!(f, "Pending approval not found"),
+            Self::Unsynchronized => write!(f, "Not available in network games and recordings, it would desynchronize them"),
+            Self::TokenRequired => write!(f, "A token is required"),
+            Self::NotModerator => write!(f, "Only moderator tokens may do this")
         }
     }
 }
@@ -83,8 +254,15 @@ impl std::fmt::Display for ErrorCode {
 impl From<ErrorCode> for StatusCode {
     fn from(value: ErrorCode) -> Self {
         match value {
-            ErrorCode::NoDebugActive | ErrorCode::NotHost | ErrorCode::NoScenario | ErrorCode::NoScriptingInReplays => StatusCode::FORBIDDEN,
+            ErrorCode::NoDebugActive | ErrorCode::NotHost | ErrorCode::NoScenario | ErrorCode::NoScriptingInReplays | ErrorCode::PolicyDenied | ErrorCode::ReadOnlyKey | ErrorCode::Unsynchronized | ErrorCode::NotModerator => StatusCode::FORBIDDEN,
+            ErrorCode::InvalidToken | ErrorCode::TokenRequired => StatusCode::UNAUTHORIZED,
             ErrorCode::ScriptParseError => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::IncompatibleBuild | ErrorCode::Paused => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::ObjectNotFound | ErrorCode::ScheduleEntryNotFound | ErrorCode::UnknownAction | ErrorCode::HeldItemNotFound | ErrorCode::KeyNotFound | ErrorCode::PlayerNotFound | ErrorCode::ApprovalNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::InvalidStrictness | ErrorCode::InvalidContext | ErrorCode::InvalidArguments | ErrorCode::EmptyBatch | ErrorCode::InvalidValue => StatusCode::BAD_REQUEST,
+            ErrorCode::ScriptExecutionError | ErrorCode::ScriptRejected => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::NotExecuted => StatusCode::FAILED_DEPENDENCY,
+            ErrorCode::RelayFailed => StatusCode::BAD_GATEWAY,
             _ => StatusCode::INTERNAL_SERVER_ERROR
         }
     }
@@ -93,15 +271,414 @@ impl From<ErrorCode> for StatusCode {
 impl warp::reject::Reject for ErrorCode {}
 
 
-async fn post_script(script: Script, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
-    instance.script.run_script(&instance, script.script.as_str())
+// Holds the request instead of running it if no scenario is running and holding is enabled.
+fn hold_if_not_running(instance: &LCTwitch, options: &HoldOptions, origin: &Origin, request: impl FnOnce() -> HeldRequest) -> Result<Option<HeldItem>, Error> {
+    instance.kill_switch().check()?;
+
+    let hold_seconds = options.hold.unwrap_or(instance.config().hold_seconds() as u64);
+    if hold_seconds == 0 || instance.script()?.is_running() {
+        return Ok(None);
+    }
+
+    Ok(Some(instance.holding_queue().hold(request(), origin.clone(), hold_seconds, options.reference.clone())))
+}
+
+// Refunds the redemption behind a request that fails before it reaches the script engine, see helix.rs.
+fn refund_if_failed<T, E: reject::Reject>(instance: &LCTwitch, origin: &Origin, result: Result<T, E>) -> Result<T, Rejection> {
+    result.map_err(|e| {
+        instance.helix().resolve(origin, false);
+        reject::custom(e)
+    })
+}
+
+// The redemption is fulfilled or refunded depending on the result, see helix.rs, so only the policy's redemption tokens
+// may pass one. For other tokens, the reward is ignored and the reference is only the client's id for the request.
+fn with_hold_options(instance: &LCTwitch, origin: Origin, hold: &HoldOptions) -> Origin {
+    let redemptions = origin.token.as_ref().map_or(false, |token| instance.policy().is_redemption_token(token));
+
+    Origin {
+        reference: hold.reference.clone(),
+        reward: hold.reward.clone().filter(|_| redemptions),
+        ..origin
+    }
+}
+
+// Requests that never reach script.rs are audited here, i.e. refused scripts and player controls.
+fn audit(instance: &Arc<LCTwitch>, time: u64, origin: &Origin, script: &str, result: Result<String, Error>) {
+    let entry = AuditEntry {
+        time,
+        frame: instance.script().ok().filter(|script| script.is_running()).and_then(|script| script.frame_counter()),
+        origin: origin.clone(),
+        script: script.to_owned(),
+        result: result.into(),
+        duration: scheduler::now().saturating_sub(time)
+    };
+
+    if let Err(e) = instance.audit().record(&entry) {
+        instance.log_in_main_thread(format!("LCTwitch: Could not write the audit log: {}", e));
+    }
+}
+
+// Binds the arguments and rejects what would fail anyway now, instead of when the script is run.
+fn prepare_script(instance: &Arc<LCTwitch>, origin: &Origin, script: &Script) -> Result<String, Error> {
+    // Linted before the arguments are declared in front of it, so that the positions match the script as it was sent.
+    let checked = instance.config().lint_rules().check(&script.script)
+        .and_then(|_| script.options.strictness.map_or(Ok(()), |strictness| C4AulScriptStrict::try_from(strictness).map(|_| ()).map_err(Error::from)))
+        .and_then(|_| args::bind(&script.script, &script.args));
+
+    checked.map_err(|e| {
+        audit(instance, scheduler::now(), origin, &script.script, Err(e.clone()));
+        e
+    })
+}
+
+// Outside of loopback, anyone on the network can reach the server, so every request needs a token from the policy.
+async fn require_token_if_remote(authorization: Option<String>, instance: Arc<LCTwitch>) -> Result<(), Rejection> {
+    if instance.config().listen_address().is_loopback() {
+        return Ok(());
+    }
+
+    match authorization.as_deref().and_then(|authorization| authorization.strip_prefix("Bearer ")).and_then(|secret| instance.policy().token_name(secret)) {
+        Some(_) => Ok(()),
+        None => Err(reject::custom(ErrorCode::TokenRequired))
+    }
+}
+
+// Maps the bearer token of a request to its name in the policy. Requests without a token are anonymous.
+async fn authenticate(authorization: Option<String>, user: Option<String>, instance: Arc<LCTwitch>) -> Result<Origin, Rejection> {
+    let token = match authorization {
+        Some(authorization) => authorization.strip_prefix("Bearer ")
+            .and_then(|secret| instance.policy().token_name(secret))
+            .map(|name| Some(name.to_owned()))
+            .ok_or_else(|| reject::custom(ErrorCode::InvalidToken))?,
+        None => None
+    };
+
+    // Only the tokens the policy reserves for relays mark requests as relayed, so that clients cannot claim it. The
+    // user is only taken from relays as well, which pass on the one the client's LCTwitch was given.
+    let relayed = token.as_ref().map_or(false, |token| instance.policy().is_relay_token(token));
+
+    Ok(Origin {
+        user: user.filter(|_| relayed),
+        ..Origin::new(if relayed { Source::Relay } else { Source::Http }, token)
+    })
+}
+
+async fn post_script(origin: Origin, request: ScriptRequest, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    let origin = with_hold_options(&instance, origin, &request.hold);
+
+    let script_engine = refund_if_failed(&instance, &origin, instance.script())?;
+    let script_text = refund_if_failed(&instance, &origin, prepare_script(&instance, &origin, &request.script))?;
+
+    if let Some(response) = refund_if_failed(&instance, &origin, relay::forward(&instance, "action/script", &request, &origin).await)? {
+        return Ok(response);
+    }
+
+    let script = request.script;
+
+    let options = script.options.clone();
+    if let Some(item) = refund_if_failed(&instance, &origin, hold_if_not_running(&instance, &request.hold, &origin, || HeldRequest::Script { script }))? {
+        return Ok(reply::with_status(reply::json(&item), StatusCode::ACCEPTED).into_response());
+    }
+
+    script_engine.run_script(&instance, &script_text, &options, &origin)
         .await
         .map_or_else(
-            |e| Err(reject::custom(Error::from(e.to_string()))),
-            |result| Ok(reply::json(&ScriptReply { result }))
+            |e| Err(reject::custom(Error::from(e))),
+            |result| Ok(reply::json(&ScriptReply { result }).into_response())
         )
 }
 
+async fn post_batch(origin: Origin, batch: Batch, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    if batch.scripts.is_empty() {
+        return Err(reject::custom(ErrorCode::EmptyBatch));
+    }
+
+    let script_engine = instance.script().map_err(reject::custom)?;
+    let scripts = batch.scripts.iter()
+        .enumerate()
+        .map(|(index, script)| args::bind(&script.script, &script.args).map_err(|e| {
+            let e = Error { message: format!("scripts[{}].{}", index, e.message), ..e };
+            audit(&instance, scheduler::now(), &origin, &script.script, Err(e.clone()));
+            e
+        }))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(reject::custom)?;
+
+    if let Some(response) = relay::forward(&instance, "action/batch", &batch, &origin).await.map_err(reject::custom)? {
+        return Ok(response);
+    }
+
+    let results = script_engine.run_batch(&instance, &scripts, &batch.options, &origin, batch.stop_on_error)
+        .await
+        .map_err(|e| reject::custom(Error::from(e)))?
+        .into_iter()
+        .map(|result| result.map_err(Error::from).into())
+        .collect();
+
+    Ok(reply::json(&BatchReply { results }).into_response())
+}
+
+async fn post_action(name: String, origin: Origin, request: ActionRequest, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    let origin = with_hold_options(&instance, origin, &request.hold);
+
+    // The host's action catalog is the one that counts.
+    if let Some(response) = refund_if_failed(&instance, &origin, relay::forward(&instance, &format!("action/run/{}", name), &request, &origin).await)? {
+        return Ok(response);
+    }
+
+    // Reject unknown actions and bad arguments now instead of when the held item is delivered.
+    refund_if_failed(&instance, &origin, actions::prepare(&instance, &name, &request.args))?;
+
+    if actions::requires_approval(&instance, &name) {
+        refund_if_failed(&instance, &origin, instance.kill_switch().check())?;
+
+        let item = instance.approvals().submit(name, request.args, origin, request.hold.reference);
+        instance.events().publish(events::Event::ApprovalRequested { item: item.clone() });
+
+        return Ok(reply::with_status(reply::json(&item), StatusCode::ACCEPTED).into_response());
+    }
+
+    let args = request.args.clone();
+    if let Some(item) = refund_if_failed(&instance, &origin, hold_if_not_running(&instance, &request.hold, &origin, || HeldRequest::Action { action: name.clone(), args }))? {
+        return Ok(reply::with_status(reply::json(&item), StatusCode::ACCEPTED).into_response());
+    }
+
+    actions::run(instance, &name, &request.args, origin)
+        .await
+        .map_or_else(
+            |e| Err(reject::custom(e)),
+            |result| Ok(reply::json(&ScriptReply { result }).into_response())
+        )
+}
+
+async fn post_control(origin: Origin, request: ControlRequest, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    let origin = Origin {
+        control: true,
+        ..origin
+    };
+
+    if let Some(response) = relay::forward(&instance, "action/control", &request, &origin).await.map_err(reject::custom)? {
+        return Ok(response);
+    }
+
+    let time = scheduler::now();
+    let result = match instance.control() {
+        Ok(control) => control.send(&instance, &request, &origin).await,
+        Err(e) => Err(e)
+    };
+
+    // Recorded like a script, with the request in place of the script text.
+    audit(&instance, time, &origin, &serde_json::to_string(&request).unwrap_or_default(), result.clone().map(|_| String::new()));
+
+    result
+        .map(|_| StatusCode::NO_CONTENT.into_response())
+        .map_err(reject::custom)
+}
+
+async fn get_automation(instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&instance.automation().status(&instance)))
+}
+
+async fn set_automation_paused(paused: bool, origin: Origin, query: PauseQuery, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    instance.automation().set_paused(query.target, paused);
+    Ok(reply::json(&instance.automation().status(&instance)))
+}
+
+async fn get_approvals(instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&instance.approvals().items()))
+}
+
+fn require_token(origin: &Origin) -> Result<(), Rejection> {
+    match origin.token {
+        Some(_) => Ok(()),
+        None => Err(reject::custom(ErrorCode::TokenRequired))
+    }
+}
+
+// Like in chat, deciding on what viewers asked for and pausing LCTwitch is up to the moderators, i.e. the tokens the
+// policy names as such.
+fn require_moderator(instance: &LCTwitch, origin: &Origin) -> Result<(), Rejection> {
+    require_token(origin)?;
+
+    match origin.token.as_ref().filter(|token| instance.policy().is_moderator_token(token)) {
+        Some(_) => Ok(()),
+        None => Err(reject::custom(ErrorCode::NotModerator))
+    }
+}
+
+async fn put_approval(id: u64, origin: Origin, edit: ApprovalEdit, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    let item = instance.approvals().get(id).ok_or_else(|| reject::custom(ErrorCode::ApprovalNotFound))?;
+
+    // Only the given arguments are changed, the others stay as the viewer sent them.
+    let mut args = item.args;
+    args.extend(edit.args);
+    actions::prepare(&instance, &item.action, &args).map_err(reject::custom)?;
+
+    instance.approvals().set_args(id, args)
+        .map(|item| reply::json(&item))
+        .ok_or_else(|| reject::custom(ErrorCode::ApprovalNotFound))
+}
+
+async fn post_approve(id: u64, origin: Origin, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    // Items stay pending if they cannot run right now, so that they can be approved again later.
+    instance.kill_switch().check().map_err(reject::custom)?;
+    if !instance.script().map_err(reject::custom)?.is_running() {
+        return Err(reject::custom(ErrorCode::NoScenario));
+    }
+
+    let item = instance.approvals().take(id).ok_or_else(|| reject::custom(ErrorCode::ApprovalNotFound))?;
+    let result = actions::run(instance.clone(), &item.action, &item.args, item.origin).await;
+
+    instance.events().publish(events::Event::ApprovalResult {
+        id: item.id,
+        reference: item.reference,
+        by: origin.user.or(origin.token),
+        result: result.clone().into()
+    });
+
+    result
+        .map(|result| reply::json(&ScriptReply { result }))
+        .map_err(reject::custom)
+}
+
+async fn post_reject(id: u64, origin: Origin, query: RejectQuery, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    let item = instance.approvals().take(id).ok_or_else(|| reject::custom(ErrorCode::ApprovalNotFound))?;
+
+    instance.helix().resolve(&item.origin, false);
+    instance.events().publish(events::Event::ApprovalRejected {
+        id: item.id,
+        reference: item.reference,
+        by: origin.user.or(origin.token),
+        reason: query.reason,
+        refund: true
+    });
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_kill_switch(instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&instance.kill_switch().status()))
+}
+
+async fn post_pause(origin: Origin, query: KillSwitchQuery, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    let by = origin.user.or(origin.token);
+    let refund = query.refund.unwrap_or(instance.config().pause_refund());
+
+    killswitch::pause(&instance, by, query.reason, refund)
+        .map(|status| reply::json(&status))
+        .map_err(|e| reject::custom(Error::from(e.to_string())))
+}
+
+async fn post_resume(origin: Origin, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+    Ok(reply::json(&killswitch::resume(&instance, origin.user.or(origin.token))))
+}
+
+async fn get_schedule(instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&instance.scheduler().entries()))
+}
+
+async fn post_schedule(origin: Origin, request: ScheduleRequest, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    instance.kill_switch().check().map_err(reject::custom)?;
+
+    let origin = Origin { source: Source::Schedule, ..origin };
+    prepare_script(&instance, &origin, &request.script).map_err(reject::custom)?;
+
+    instance.scheduler().schedule(request.script, request.when, origin)
+        .map(|entry| reply::with_status(reply::json(&entry), StatusCode::CREATED))
+        .map_err(|e| reject::custom(Error::from(e.to_string())))
+}
+
+async fn delete_schedule(id: u64, origin: Origin, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    match instance.scheduler().cancel(id) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(reject::custom(ErrorCode::ScheduleEntryNotFound)),
+        Err(e) => Err(reject::custom(Error::from(e.to_string())))
+    }
+}
+
+async fn get_held(instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&instance.holding_queue().items()))
+}
+
+async fn delete_held(id: u64, origin: Origin, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    let item = instance.holding_queue().cancel(id).ok_or_else(|| reject::custom(ErrorCode::HeldItemNotFound))?;
+
+    instance.helix().resolve(&item.origin, false);
+    instance.events().publish(events::Event::HeldCancelled {
+        id: item.id,
+        reference: item.reference,
+        refund: true
+    });
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_values(instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&instance.kv().entries()))
+}
+
+async fn get_value(key: String, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    instance.kv().get(&key)
+        .map(|value| reply::json(&value))
+        .ok_or_else(|| reject::custom(ErrorCode::KeyNotFound))
+}
+
+// Also used for removing keys, which is the same as setting them to null.
+fn set_value(instance: &LCTwitch, key: String, value: Value) -> Result<(), Rejection> {
+    instance.kv().set(&key, value.clone()).map_err(reject::custom)?;
+    instance.kv().save().map_err(|e| reject::custom(Error::from(e.to_string())))?;
+
+    instance.events().publish(events::Event::ValueChanged { key, value });
+    Ok(())
+}
+
+async fn put_value(key: String, origin: Origin, value: Value, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    set_value(&instance, key, value)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_value(key: String, origin: Origin, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_moderator(&instance, &origin)?;
+
+    if instance.kv().get(&key).is_none() {
+        return Err(reject::custom(ErrorCode::KeyNotFound));
+    }
+
+    set_value(&instance, key, Value::Null)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_recent_events(instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&instance.events().recent()))
+}
+
+// Contains every script along with who asked for it, so it is not for anonymous callers.
+async fn get_audit(origin: Origin, query: AuditQuery, instance: Arc<LCTwitch>) -> Result<impl Reply, Rejection> {
+    require_token(&origin)?;
+
+    instance.audit().query(&query)
+        .map(|entries| reply::json(&entries))
+        .map_err(|e| reject::custom(Error::from(e.to_string())))
+}
+
 async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
     let reply_error_from_code = |code: ErrorCode| Ok(reply::with_status(reply::json(&Error::from(code)), code.into()));
 
@@ -120,20 +697,231 @@ pub async fn run_server(instance: Arc<LCTwitch>, rx: Receiver<()>) {
     let instance_clone = instance.clone();
     let instance_filter = warp::any().map(move || instance_clone.clone());
 
+    let origin = warp::header::optional::<String>("authorization")
+        .and(warp::header::optional::<String>(relay::USER_HEADER))
+        .and(instance_filter.clone())
+        .and_then(authenticate);
+
+    let script = warp::path("script")
+        .and(warp::post())
+        .and(origin.clone())
+        .and(warp::body::json())
+        .and(instance_filter.clone())
+        .and_then(post_script);
+
+    let batch = warp::path("batch")
+        .and(warp::post())
+        .and(origin.clone())
+        .and(warp::body::json())
+        .and(instance_filter.clone())
+        .and_then(post_batch);
+
+    let action = warp::path("run")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(origin.clone())
+        .and(warp::body::json())
+        .and(instance_filter.clone())
+        .and_then(post_action);
+
+    let control = warp::path("control")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(origin.clone())
+        .and(warp::body::json())
+        .and(instance_filter.clone())
+        .and_then(post_control);
+
+    let automation_status = warp::path::end()
+        .and(warp::get())
+        .and(instance_filter.clone())
+        .and_then(get_automation);
+
+    let pause_automation = warp::path("pause").map(|| true)
+        .or(warp::path("resume").map(|| false))
+        .unify()
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(origin.clone())
+        .and(warp::query::<PauseQuery>())
+        .and(instance_filter.clone())
+        .and_then(set_automation_paused);
+
+    let list_approvals = warp::path::end()
+        .and(warp::get())
+        .and(instance_filter.clone())
+        .and_then(get_approvals);
+
+    let edit_approval = warp::path::param::<u64>()
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(origin.clone())
+        .and(warp::body::json())
+        .and(instance_filter.clone())
+        .and_then(put_approval);
+
+    let approve_item = warp::path::param::<u64>()
+        .and(warp::path("approve"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(origin.clone())
+        .and(instance_filter.clone())
+        .and_then(post_approve);
+
+    let reject_item = warp::path::param::<u64>()
+        .and(warp::path("reject"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(origin.clone())
+        .and(warp::query::<RejectQuery>())
+        .and(instance_filter.clone())
+        .and_then(post_reject);
+
+    let kill_switch_status = warp::path::end()
+        .and(warp::get())
+        .and(instance_filter.clone())
+        .and_then(get_kill_switch);
+
+    let pause = warp::path("pause")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(origin.clone())
+        .and(warp::query::<KillSwitchQuery>())
+        .and(instance_filter.clone())
+        .and_then(post_pause);
+
+    let resume = warp::path("resume")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(origin.clone())
+        .and(instance_filter.clone())
+        .and_then(post_resume);
+
+    let list_schedule = warp::path::end()
+        .and(warp::get())
+        .and(instance_filter.clone())
+        .and_then(get_schedule);
+
+    let add_schedule = warp::path::end()
+        .and(warp::post())
+        .and(origin.clone())
+        .and(warp::body::json())
+        .and(instance_filter.clone())
+        .and_then(post_schedule);
+
+    let cancel_schedule = warp::path::param::<u64>()
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(origin.clone())
+        .and(instance_filter.clone())
+        .and_then(delete_schedule);
+
+    let list_held = warp::path::end()
+        .and(warp::get())
+        .and(instance_filter.clone())
+        .and_then(get_held);
+
+    let cancel_held = warp::path::param::<u64>()
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(origin.clone())
+        .and(instance_filter.clone())
+        .and_then(delete_held);
+
+    let list_values = warp::path::end()
+        .and(warp::get())
+        .and(instance_filter.clone())
+        .and_then(get_values);
+
+    let read_value = warp::path::param::<String>()
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(instance_filter.clone())
+        .and_then(get_value);
+
+    let write_value = warp::path::param::<String>()
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(origin.clone())
+        .and(warp::body::json())
+        .and(instance_filter.clone())
+        .and_then(put_value);
+
+    let remove_value = warp::path::param::<String>()
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(origin.clone())
+        .and(instance_filter.clone())
+        .and_then(delete_value);
+
+    let events = warp::path("events")
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(instance_filter.clone())
+        .map(|ws: warp::ws::Ws, instance: Arc<LCTwitch>| ws.on_upgrade(move |socket| events::stream(socket, instance)));
+
+    let recent_events = warp::path("events")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(instance_filter.clone())
+        .and_then(get_recent_events);
+
+    let audit = warp::path("audit")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(origin.clone())
+        .and(warp::query::<AuditQuery>())
+        .and(instance_filter.clone())
+        .and_then(get_audit);
+
+    let remote = warp::header::optional::<String>("authorization")
+        .and(instance_filter.clone())
+        .and_then(require_token_if_remote)
+        .untuple_one();
+
     let route = warp::path("v1")
-        .and(warp::path("action"))
-            .and(warp::path("script"))
-                .and(warp::post())
-                .and(warp::body::json())
-                .and(instance_filter)
-                .and_then(post_script)
-                .recover(handle_rejection);
+        .and(remote)
+        .and(
+            warp::path("action")
+                .and(script.or(batch).or(action).or(control))
+            .or(warp::path("schedule")
+                .and(list_schedule.or(add_schedule).or(cancel_schedule)))
+            .or(warp::path("automation")
+                .and(automation_status.or(pause_automation)))
+            .or(warp::path("control")
+                .and(kill_switch_status.or(pause).or(resume)))
+            .or(warp::path("queue")
+                .and(list_held.or(cancel_held)))
+            .or(warp::path("approvals")
+                .and(list_approvals.or(edit_approval).or(approve_item).or(reject_item)))
+            .or(warp::path("kv")
+                .and(list_values.or(read_value).or(write_value).or(remove_value)))
+            .or(events)
+            .or(recent_events)
+            .or(audit)
+        )
+        .recover(handle_rejection);
 
                 
-    let (_, server) = warp::serve(route)
-        .bind_with_graceful_shutdown(([127, 0, 0, 1], instance.config().port()), async move {
-            rx.await.unwrap();
-        });
+    let address = (instance.config().listen_address(), instance.config().port());
+    let shutdown = async move {
+        rx.await.unwrap();
+    };
 
-    server.await
+    match instance.config().tls_files() {
+        Some((certificate, key)) => {
+            let (_, server) = warp::serve(route)
+                .tls()
+                .cert_path(certificate)
+                .key_path(key)
+                .bind_with_graceful_shutdown(address, shutdown);
+
+            server.await
+        },
+        None => {
+            let (_, server) = warp::serve(route).bind_with_graceful_shutdown(address, shutdown);
+            server.await
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,120 @@
+use std::{error::Error, ffi::c_void};
+
+use crate::pdbinfo::SymbolLocation;
+
+pub const IMAGE_SCN_CNT_CODE: u32 = 0x00000020;
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x20000000;
+
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub name: String,
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+    pub characteristics: u32
+}
+
+impl Section {
+    pub fn is_code(&self) -> bool {
+        self.characteristics & (IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE) != 0
+    }
+}
+
+pub struct Image {
+    base: *const u8,
+    time_date_stamp: u32,
+    size_of_image: u32,
+    sections: Vec<Section>
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, Box<dyn Error>> {
+    Ok(u16::from_le_bytes(data.get(offset..offset + 2).ok_or("Truncated PE header")?.try_into()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    Ok(u32::from_le_bytes(data.get(offset..offset + 4).ok_or("Truncated PE header")?.try_into()?))
+}
+
+impl Image {
+    // Reads the headers of an image that has been mapped by the loader.
+    pub unsafe fn from_base(base: *const u8) -> Result<Image, Box<dyn Error>> {
+        let nt_headers = read_u32(std::slice::from_raw_parts(base, 0x40), 0x3C)? as usize;
+        let file_header = std::slice::from_raw_parts(base.add(nt_headers), 24);
+        let section_count = read_u16(file_header, 6)? as usize;
+        let optional_header_size = read_u16(file_header, 20)? as usize;
+
+        let headers_size = nt_headers + 24 + optional_header_size + section_count * 40;
+        Self::parse(base, std::slice::from_raw_parts(base, headers_size))
+    }
+
+    pub fn parse(base: *const u8, headers: &[u8]) -> Result<Image, Box<dyn Error>> {
+        if headers.get(0..2) != Some(b"MZ".as_slice()) {
+            return Err("Missing DOS header".into());
+        }
+
+        let nt_headers = read_u32(headers, 0x3C)? as usize;
+        if headers.get(nt_headers..nt_headers + 4) != Some(b"PE\0\0".as_slice()) {
+            return Err("Missing PE header".into());
+        }
+
+        let file_header = nt_headers + 4;
+        let section_count = read_u16(headers, file_header + 2)? as usize;
+        let time_date_stamp = read_u32(headers, file_header + 4)?;
+        let optional_header_size = read_u16(headers, file_header + 16)? as usize;
+        let optional_header = file_header + 20;
+        let size_of_image = read_u32(headers, optional_header + 56)?;
+        let section_table = optional_header + optional_header_size;
+
+        let sections = (0..section_count)
+            .map(|index| {
+                let header = section_table + index * 40;
+                let name = headers.get(header..header + 8).ok_or("Truncated section table")?;
+
+                Ok(Section {
+                    name: String::from_utf8_lossy(name.split(|&c| c == 0).next().unwrap_or_default()).into_owned(),
+                    virtual_size: read_u32(headers, header + 8)?,
+                    virtual_address: read_u32(headers, header + 12)?,
+                    characteristics: read_u32(headers, header + 36)?
+                })
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+        Ok(Image {
+            base,
+            time_date_stamp,
+            size_of_image,
+            sections
+        })
+    }
+
+    pub fn base(&self) -> *const u8 {
+        self.base
+    }
+
+    // Identifies a build the same way symbol servers do.
+    pub fn fingerprint(&self) -> String {
+        format!("{:08X}{:X}", self.time_date_stamp, self.size_of_image)
+    }
+
+    pub fn rva_of(&self, address: *const c_void) -> u32 {
+        (address as usize - self.base as usize) as u32
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn rva(&self, location: SymbolLocation) -> Result<u32, Box<dyn Error>> {
+        let section = location.section.checked_sub(1)
+            .and_then(|index| self.sections.get(index as usize))
+            .ok_or_else(|| format!("Invalid section in symbol location {}", location))?;
+
+        Ok(section.virtual_address + location.offset)
+    }
+
+    pub fn address(&self, location: SymbolLocation) -> Result<*const c_void, Box<dyn Error>> {
+        Ok(unsafe { self.base.add(self.rva(location)? as usize) } as *const c_void)
+    }
+}
+
+unsafe impl Send for Image {}
+unsafe impl Sync for Image {}
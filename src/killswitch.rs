@@ -0,0 +1,192 @@
+use std::{error::Error, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use windows::Win32::UI::Input::KeyboardAndMouse::{HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, RegisterHotKey, VK_F1, VK_PAUSE, VK_SCROLL};
+
+use crate::{LCTwitch, events::Event, http::ErrorCode, scheduler::now};
+
+pub(crate) const HOTKEY_ID: i32 = 0x4C54;
+
+// Set by the window procedure, which cannot reach the instance.
+static HOTKEY_PRESSED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct KillSwitchStatus {
+    pub paused: bool,
+    #[serde(default)]
+    pub since: Option<u64>,
+    // A token name, a chat moderator or "hotkey".
+    #[serde(default)]
+    pub by: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>
+}
+
+// Stops everything at once. While paused, nothing is sent to the game, and requests are rejected instead of
+// being held or scheduled. Only an explicit resume lifts it.
+pub struct KillSwitch {
+    status: Mutex<KillSwitchStatus>
+}
+
+impl KillSwitch {
+    pub fn new() -> KillSwitch {
+        KillSwitch {
+            status: Mutex::new(KillSwitchStatus::default())
+        }
+    }
+
+    pub fn status(&self) -> KillSwitchStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn check(&self) -> Result<(), ErrorCode> {
+        if self.status.lock().unwrap().paused {
+            Err(ErrorCode::Paused)
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+// Cancels held, pending and scheduled requests. refund tells clients whether to refund the redemptions behind them.
+pub fn pause(instance: &LCTwitch, by: Option<String>, reason: Option<String>, refund: bool) -> Result<KillSwitchStatus, Box<dyn Error>> {
+    let was_paused = {
+        let mut status = instance.kill_switch().status.lock().unwrap();
+        let was_paused = status.paused;
+
+        if !was_paused {
+            *status = KillSwitchStatus {
+                paused: true,
+                since: Some(now()),
+                by: by.clone(),
+                reason: reason.clone()
+            };
+        }
+
+        was_paused
+    };
+
+    // Pausing again still cancels whatever was queued in the meantime, e.g. by a client that raced the pause.
+    for item in instance.holding_queue().take_all() {
+        if refund {
+            instance.helix().resolve(&item.origin, false);
+        }
+
+        instance.events().publish(Event::HeldCancelled {
+            id: item.id,
+            reference: item.reference,
+            refund
+        });
+    }
+
+    for item in instance.approvals().take_all() {
+        if refund {
+            instance.helix().resolve(&item.origin, false);
+        }
+
+        instance.events().publish(Event::ApprovalRejected {
+            id: item.id,
+            reference: item.reference,
+            by: by.clone(),
+            reason: reason.clone(),
+            refund
+        });
+    }
+
+    let cancelled = instance.scheduler().cancel_all();
+
+    if !was_paused {
+        instance.events().publish(Event::Paused {
+            by: by.clone(),
+            reason: reason.clone(),
+            refund
+        });
+
+        instance.twitch().say(&match &reason {
+            Some(reason) => format!("All viewer actions have been stopped: {}", reason),
+            None => "All viewer actions have been stopped".to_owned()
+        });
+    }
+
+    cancelled?;
+    Ok(instance.kill_switch().status())
+}
+
+pub fn resume(instance: &LCTwitch, by: Option<String>) -> KillSwitchStatus {
+    let was_paused = std::mem::take(&mut *instance.kill_switch().status.lock().unwrap()).paused;
+
+    if was_paused {
+        instance.events().publish(Event::Resumed { by });
+        instance.twitch().say("Viewer actions are enabled again");
+    }
+
+    instance.kill_switch().status()
+}
+
+pub(crate) fn hotkey_pressed() {
+    HOTKEY_PRESSED.store(true, Ordering::SeqCst);
+}
+
+// Parses e.g. "Ctrl+Shift+F12". Keys are letters, digits, F1 to F24, Pause and ScrollLock.
+fn parse_hotkey(hotkey: &str) -> Result<(HOT_KEY_MODIFIERS, u32), Box<dyn Error>> {
+    let mut modifiers = MOD_NOREPEAT;
+    let mut key = None;
+
+    for part in hotkey.split('+').map(str::trim) {
+        match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => modifiers |= MOD_CONTROL,
+            "alt" => modifiers |= MOD_ALT,
+            "shift" => modifiers |= MOD_SHIFT,
+            "win" => modifiers |= MOD_WIN,
+            "pause" => key = Some(VK_PAUSE.0 as u32),
+            "scrolllock" => key = Some(VK_SCROLL.0 as u32),
+            name => key = Some(match name.strip_prefix('f').and_then(|number| number.parse::<u32>().ok()) {
+                Some(number @ 1..=24) => VK_F1.0 as u32 + number - 1,
+                _ if name.len() == 1 && name.chars().all(|c| c.is_ascii_alphanumeric()) => name.to_ascii_uppercase().as_bytes()[0] as u32,
+                _ => return Err(format!("Unknown key {} in {}", part, hotkey).into())
+            })
+        }
+    }
+
+    Ok((modifiers, key.ok_or_else(|| format!("No key in {}", hotkey))?))
+}
+
+fn register_hotkey(instance: &Arc<LCTwitch>, hotkey: &str) -> Result<(), Box<dyn Error>> {
+    let (modifiers, key) = parse_hotkey(hotkey)?;
+    let window = instance.main_window().ok_or("Hotkeys need a game window")?;
+
+    // Hotkeys can only be registered by the thread that owns the window.
+    let message_instance = instance.clone();
+    instance.run_in_main_thread(move || {
+        if !unsafe { RegisterHotKey(window, HOTKEY_ID, modifiers, key) }.as_bool() {
+            let _ = message_instance.log(&format!("LCTwitch: Could not register the pause hotkey: {}", windows::core::Error::from_win32()));
+        }
+    });
+
+    Ok(())
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let hotkey = match instance.config().pause_hotkey() {
+        Some(hotkey) => hotkey,
+        None => return
+    };
+
+    if let Err(e) = register_hotkey(&instance, hotkey) {
+        instance.log_in_main_thread(format!("LCTwitch: Pause hotkey disabled: {}", e));
+        return;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        interval.tick().await;
+
+        if HOTKEY_PRESSED.swap(false, Ordering::SeqCst) {
+            if let Err(e) = pause(&instance, Some("hotkey".to_owned()), None, instance.config().pause_refund()) {
+                instance.log_in_main_thread(format!("LCTwitch: Could not cancel scheduled scripts: {}", e));
+            }
+        }
+    }
+}
\ No newline at end of file
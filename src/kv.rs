@@ -0,0 +1,75 @@
+use std::{collections::BTreeMap, error::Error, path::PathBuf, sync::RwLock};
+
+use serde_json::Value;
+
+use crate::{args, http::{self, ErrorCode}};
+
+// Keys with this prefix are maintained by LCTwitch itself. They are neither saved nor writable by clients.
+const RESERVED_PREFIX: &str = "twitch.";
+
+// Values shared between LCTwitch, its clients and scenario scripts. Only values scenario scripts can represent
+// are accepted, and storing nil removes a key, since reading a missing key returns nil anyway.
+// Without a file, values only live as long as the game.
+pub struct KeyValueStore {
+    values: RwLock<BTreeMap<String, Value>>,
+    file: Option<PathBuf>
+}
+
+impl KeyValueStore {
+    pub fn new(file: Option<PathBuf>) -> Result<KeyValueStore, Box<dyn Error>> {
+        let values = match &file {
+            Some(path) if path.exists() => serde_json::from_slice::<BTreeMap<String, Value>>(&std::fs::read(path)?)
+                .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?,
+            _ => BTreeMap::new()
+        };
+
+        Ok(KeyValueStore {
+            values: RwLock::new(values),
+            file
+        })
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        let values = self.values.read().unwrap().iter()
+            .filter(|(key, _)| !key.starts_with(RESERVED_PREFIX))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<BTreeMap<_, _>>();
+
+        Ok(std::fs::write(path, serde_json::to_vec_pretty(&values)?)?)
+    }
+
+    pub fn entries(&self) -> BTreeMap<String, Value> {
+        self.values.read().unwrap().clone()
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.values.read().unwrap().get(key).cloned()
+    }
+
+    // Only changes the value in memory, call save() afterwards to persist it.
+    pub fn set(&self, key: &str, value: Value) -> Result<(), http::Error> {
+        if key.starts_with(RESERVED_PREFIX) {
+            return Err(ErrorCode::ReadOnlyKey.into());
+        }
+
+        args::to_literal(&value, key).map_err(|e| http::Error { code: ErrorCode::InvalidValue, ..e })?;
+        self.set_reserved(key, value);
+        Ok(())
+    }
+
+    pub(crate) fn set_reserved(&self, key: &str, value: Value) {
+        let mut values = self.values.write().unwrap();
+
+        if value.is_null() {
+            values.remove(key);
+        }
+        else {
+            values.insert(key.to_owned(), value);
+        }
+    }
+}
\ No newline at end of file
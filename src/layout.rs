@@ -0,0 +1,169 @@
+use std::{cell::{Cell, RefCell}, collections::BTreeMap, error::Error, ffi::c_void, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{image::Image, pdbinfo::PdbInfo, resolver::{self, Field, Member, Resolver, Step}};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GlobalPath {
+    pub root: u32,
+    pub steps: Vec<Step>,
+    pub size: Option<usize>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FieldPath {
+    pub steps: Vec<Step>,
+    pub size: usize
+}
+
+// Everything LCTwitch looked up for one build of the game, keyed by the query.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LayoutSnapshot {
+    #[serde(default)]
+    pub type_sizes: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub member_offsets: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub globals: BTreeMap<String, GlobalPath>,
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldPath>,
+    #[serde(default)]
+    pub vtables: BTreeMap<String, u32>,
+    #[serde(default)]
+    pub vtable_entries: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub virtual_methods: BTreeMap<String, usize>
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct LayoutSnapshotFile {
+    builds: BTreeMap<String, LayoutSnapshot>
+}
+
+// Answers layout queries from the snapshot of a known build and falls back to the PDB,
+// which is only parsed once the first query misses.
+pub struct Layout<'a> {
+    image: &'a Image,
+    pdb_path: PathBuf,
+    pdb: RefCell<Option<PdbInfo>>,
+    snapshot: RefCell<LayoutSnapshot>,
+    modified: Cell<bool>
+}
+
+impl<'a> Layout<'a> {
+    pub fn new(image: &'a Image, pdb_path: PathBuf, snapshot: Option<LayoutSnapshot>) -> Layout<'a> {
+        Layout {
+            image,
+            pdb_path,
+            pdb: RefCell::new(None),
+            modified: Cell::new(snapshot.is_none()),
+            snapshot: RefCell::new(snapshot.unwrap_or_default())
+        }
+    }
+
+    pub fn load(image: &'a Image, pdb_path: PathBuf, snapshot_file: &Path) -> Layout<'a> {
+        let snapshot = std::fs::read(snapshot_file).ok()
+            .and_then(|contents| serde_json::from_slice::<LayoutSnapshotFile>(&contents).ok())
+            .and_then(|mut file| file.builds.remove(&image.fingerprint()));
+
+        Self::new(image, pdb_path, snapshot)
+    }
+
+    pub fn from_snapshot(&self) -> bool {
+        !self.modified.get() && self.pdb.borrow().is_none()
+    }
+
+    pub fn save(&self, snapshot_file: &Path) -> Result<(), Box<dyn Error>> {
+        if !self.modified.get() {
+            return Ok(());
+        }
+
+        let mut file = std::fs::read(snapshot_file).ok()
+            .and_then(|contents| serde_json::from_slice::<LayoutSnapshotFile>(&contents).ok())
+            .unwrap_or_default();
+
+        file.builds.insert(self.image.fingerprint(), self.snapshot.borrow().clone());
+        std::fs::write(snapshot_file, serde_json::to_vec_pretty(&file)?)?;
+
+        self.modified.set(false);
+        Ok(())
+    }
+
+    fn with_pdb<R>(&self, op: impl FnOnce(&PdbInfo) -> Result<R, Box<dyn Error>>) -> Result<R, Box<dyn Error>> {
+        let mut pdb = self.pdb.borrow_mut();
+        if pdb.is_none() {
+            *pdb = Some(PdbInfo::open(&self.pdb_path).map_err(|e| format!("Could not read {}: {}", self.pdb_path.display(), e))?);
+        }
+
+        self.modified.set(true);
+        op(pdb.as_ref().unwrap())
+    }
+
+    fn cached<K: Ord + Clone, V: Clone>(
+        &self,
+        map: impl Fn(&mut LayoutSnapshot) -> &mut BTreeMap<K, V>,
+        key: K,
+        resolve: impl FnOnce(&PdbInfo) -> Result<V, Box<dyn Error>>
+    ) -> Result<V, Box<dyn Error>> {
+        if let Some(value) = map(&mut self.snapshot.borrow_mut()).get(&key) {
+            return Ok(value.clone());
+        }
+
+        let value = self.with_pdb(resolve)?;
+        map(&mut self.snapshot.borrow_mut()).insert(key, value.clone());
+        Ok(value)
+    }
+
+    pub fn type_size(&self, name: &str) -> Result<usize, Box<dyn Error>> {
+        self.cached(|snapshot| &mut snapshot.type_sizes, name.to_owned(), |pdb| pdb.type_size(name))
+    }
+
+    pub fn member_offset(&self, type_name: &str, member_name: &str) -> Result<usize, Box<dyn Error>> {
+        self.cached(|snapshot| &mut snapshot.member_offsets, format!("{}::{}", type_name, member_name), |pdb| pdb.member_offset(type_name, member_name))
+    }
+
+    pub fn resolve<T>(&self, path: &str) -> Result<Member<T>, Box<dyn Error>> {
+        let global = self.cached(|snapshot| &mut snapshot.globals, path.to_owned(), |pdb| {
+            let (root, steps, size) = Resolver::new(pdb, self.image).resolve_raw(path)?;
+            Ok(GlobalPath { root, steps, size: Some(size) })
+        })?;
+
+        resolver::check_size::<T>(path, global.size.ok_or_else(|| format!("{} has unknown size", path))?)?;
+        Ok(Member::new(unsafe { self.image.base().add(global.root as usize) } as *const c_void, Field::from_steps(global.steps)))
+    }
+
+    pub fn resolve_field<T>(&self, type_name: &str, path: &str) -> Result<Field<T>, Box<dyn Error>> {
+        let full_path = format!("{}::{}", type_name, path);
+        let field = self.cached(|snapshot| &mut snapshot.fields, full_path.clone(), |pdb| {
+            let (steps, size) = Resolver::new(pdb, self.image).resolve_field_raw(type_name, path)?;
+            Ok(FieldPath { steps, size })
+        })?;
+
+        resolver::check_size::<T>(&full_path, field.size)?;
+        Ok(Field::from_steps(field.steps))
+    }
+
+    pub fn address(&self, path: &str) -> Result<*mut c_void, Box<dyn Error>> {
+        // Stored separately from sized paths so that a later size-checked lookup still resolves the size.
+        let global = self.cached(|snapshot| &mut snapshot.globals, format!("&{}", path), |pdb| {
+            let (root, steps) = Resolver::new(pdb, self.image).address_raw(path)?;
+            Ok(GlobalPath { root, steps, size: None })
+        })?;
+
+        resolver::evaluate_address(self.image, path, global.root, global.steps)
+    }
+
+    pub fn vtable(&self, type_name: &str) -> Result<*const *const c_void, Box<dyn Error>> {
+        let rva = self.cached(|snapshot| &mut snapshot.vtables, type_name.to_owned(), |pdb| self.image.rva(pdb.vtable(type_name)?))?;
+        Ok(unsafe { self.image.base().add(rva as usize) } as *const *const c_void)
+    }
+
+    pub fn vtable_entries(&self, type_name: &str) -> Result<usize, Box<dyn Error>> {
+        self.cached(|snapshot| &mut snapshot.vtable_entries, type_name.to_owned(), |pdb| pdb.vtable_entries(type_name))
+    }
+
+    pub fn virtual_method_index(&self, type_name: &str, method_name: &str) -> Result<usize, Box<dyn Error>> {
+        self.cached(|snapshot| &mut snapshot.virtual_methods, format!("{}::{}", type_name, method_name), |pdb| pdb.virtual_method_index(type_name, method_name))
+    }
+}
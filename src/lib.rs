@@ -4,22 +4,67 @@
 #![feature(cstr_from_bytes_until_nul)]
 #![recursion_limit = "256"]
 
-use std::{ffi::{CStr, c_char, CString, NulError}, error::Error};
-use std::sync::Arc;
+use std::{ffi::{CStr, c_char, c_void, CString, NulError}, error::Error, path::Path};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicPtr;
 
 use byte_strings::c_str;
+use actions::ActionsFile;
+use approval::ApprovalQueue;
+use audit::AuditLog;
+use automation::Automation;
 use config::Config;
-use detour::{find_function, Module};
+use control::Control;
+use events::EventBus;
+use hooks::GameHooks;
+use detour::{Detour, Module};
+use helix::Helix;
+use image::Image;
+use killswitch::KillSwitch;
+use kv::KeyValueStore;
+use layout::Layout;
+use natives::Natives;
+use policy::PolicyFile;
+use queue::HoldingQueue;
+use relay::Relay;
+use replay::ReplayAnnotations;
+use scheduler::Scheduler;
 use script::Script;
+use signature::{SignatureFile, Symbols};
+use twitch::Twitch;
 use window::WindowSubclass;
-use windows::{Win32::{System::{LibraryLoader::GetModuleHandleW, Threading::{GetCurrentThread, GetCurrentProcess, WaitForSingleObject}, Diagnostics::Debug::*}, Foundation::{BOOL, HANDLE, HWND, WPARAM, LPARAM, LRESULT, HINSTANCE, DuplicateHandle, DUPLICATE_SAME_ACCESS}, UI::{WindowsAndMessaging::{EnumWindows, GetWindowLongPtrW, GWLP_HINSTANCE, GetClassNameW, WM_USER, PostMessageA}, Shell::DefSubclassProc}}, core::PWSTR};
+use windows::{Win32::{System::{LibraryLoader::GetModuleHandleW, Threading::{GetCurrentThread, GetCurrentProcess, GetCurrentProcessId, WaitForSingleObject}}, Foundation::{BOOL, HANDLE, HWND, WPARAM, LPARAM, LRESULT, HINSTANCE, DuplicateHandle, DUPLICATE_SAME_ACCESS}, UI::{WindowsAndMessaging::{EnumWindows, GetWindow, GetWindowLongPtrW, GetWindowThreadProcessId, GWLP_HINSTANCE, GW_OWNER, GetClassNameW, GetMenu, WM_HOTKEY, WM_USER, PostMessageA}, Shell::DefSubclassProc}}};
 
+pub mod actions;
+pub mod approval;
+pub mod args;
+pub mod audit;
+pub mod automation;
 pub mod config;
-pub mod dbghelp;
+pub mod control;
 pub mod detour;
+pub mod environment;
+pub mod events;
 pub mod export;
+pub mod helix;
+pub mod hooks;
 pub mod http;
+pub mod image;
+pub mod killswitch;
+pub mod kv;
+pub mod layout;
+pub mod lint;
+pub mod natives;
+pub mod pdbinfo;
+pub mod policy;
+pub mod queue;
+pub mod relay;
+pub mod replay;
+pub mod resolver;
+pub mod scheduler;
 pub mod script;
+pub mod signature;
+pub mod twitch;
 pub mod window;
 
 type FnLog = extern "C" fn(*const c_char) -> bool;
@@ -35,36 +80,100 @@ extern "system" fn subclass_proc(window: HWND, msg: u32, wparam: WPARAM, lparam:
 
         return LRESULT(0);
     }
+    else if msg == WM_HOTKEY && wparam.0 == killswitch::HOTKEY_ID as usize {
+        killswitch::hotkey_pressed();
+        return LRESULT(0);
+    }
 
     unsafe { DefSubclassProc(window, msg, wparam, lparam) }
 }
 
+type FnGameExecute = extern "win64" fn(*mut c_void) -> bool;
+type FnApplicationExecute = extern "win64" fn(*mut c_void);
+
+static GAME_EXECUTE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static APPLICATION_EXECUTE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static MAIN_THREAD_QUEUE: Mutex<Vec<Box<dyn FnOnce() + Send>>> = Mutex::new(Vec::new());
+
+fn run_main_thread_queue() {
+    let callbacks = std::mem::take(&mut *MAIN_THREAD_QUEUE.lock().unwrap());
+    for callback in callbacks {
+        callback();
+    }
+}
+
+// Without a window to post messages to, queued callbacks are run at the start of every game frame.
+extern "win64" fn game_execute(game: *mut c_void) -> bool {
+    run_main_thread_queue();
+    unsafe { detour::original::<FnGameExecute>(&GAME_EXECUTE)(game) }
+}
+
+// Also runs in the lobby and between rounds, when there are no game frames.
+extern "win64" fn application_execute(application: *mut c_void) {
+    run_main_thread_queue();
+    unsafe { detour::original::<FnApplicationExecute>(&APPLICATION_EXECUTE)(application) }
+}
+
+struct MainThreadHooks {
+    _game_execute: Detour<FnGameExecute, FnGameExecute>,
+    _application_execute: Option<Detour<FnApplicationExecute, FnApplicationExecute>>
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MainThreadMode {
+    Fullscreen,
+    Console,
+    Headless
+}
+
+enum MainThreadDispatch {
+    Window(WindowSubclass),
+    Hook(Option<MainThreadHooks>)
+}
+
 pub struct LCTwitchMainThread {
     handle: HANDLE,
-    main_window_subclass: WindowSubclass
+    mode: MainThreadMode,
+    dispatch: MainThreadDispatch
+}
+
+struct MainWindowSearch {
+    instance: HINSTANCE,
+    process_id: u32,
+    fullscreen: HWND,
+    console: HWND
 }
 
 extern "system" fn is_main_window(window: HWND, param: LPARAM) -> BOOL {
     unsafe {
-        let arguments = &mut *std::mem::transmute::<_, *mut (HINSTANCE, HWND)>(param);
-        if HINSTANCE(GetWindowLongPtrW(window, GWLP_HINSTANCE)) != arguments.0 {
+        let search = &mut *(param.0 as *mut MainWindowSearch);
+
+        let mut process_id = 0;
+        GetWindowThreadProcessId(window, Some(&mut process_id as *mut u32));
+        if process_id != search.process_id || HINSTANCE(GetWindowLongPtrW(window, GWLP_HINSTANCE)) != search.instance {
             return true.into();
         }
 
-        let mut buffer = [0u16; 13];
-        if GetClassNameW(window, &mut buffer[0..12]) == 0 {
+        let mut buffer = [0u16; 32];
+        let length = GetClassNameW(window, &mut buffer);
+        if length == 0 {
             return true.into();
         }
 
-        if !PWSTR::from_raw(buffer.as_mut_ptr()).to_string()
-            .map_or_else(
-                |_| false,
-            |s| s == "C4Fullscreen") {
-                arguments.1 = window;
+        match String::from_utf16_lossy(&buffer[..length as usize]).as_str() {
+            "C4Fullscreen" => {
+                search.fullscreen = window;
                 return false.into();
-            }
-        
-        return true.into();
+            },
+            // The editor console is a dialog without an owner; the property dialogs it opens are owned by it.
+            // Unlike message boxes, which may not have an owner either, it has a menu bar.
+            "#32770" if search.console == Default::default() && GetWindow(window, GW_OWNER) == Default::default() && GetMenu(window) != Default::default() => {
+                search.console = window;
+            },
+            _ => {}
+        }
+
+        true.into()
     }
 }
 
@@ -72,12 +181,14 @@ impl LCTwitchMainThread {
     pub fn new() -> Result<LCTwitchMainThread, Box<dyn std::error::Error>> {
         let clonk_handle = unsafe { GetModuleHandleW(None)? };
 
-        let mut arguments = (clonk_handle, Default::default());
-        unsafe { EnumWindows(Some(is_main_window), LPARAM(&mut arguments as *mut (HINSTANCE, HWND) as isize)) };
+        let mut search = MainWindowSearch {
+            instance: clonk_handle,
+            process_id: unsafe { GetCurrentProcessId() },
+            fullscreen: Default::default(),
+            console: Default::default()
+        };
 
-        if arguments.1 == Default::default() {
-            return Err("Could not find window handle".into());
-        }
+        unsafe { EnumWindows(Some(is_main_window), LPARAM(&mut search as *mut MainWindowSearch as isize)) };
 
         let mut handle: HANDLE = Default::default();
 
@@ -95,42 +206,198 @@ impl LCTwitchMainThread {
             return Err("Could not duplicate thread handle".into());
         }
 
+        let (mode, dispatch) = if search.fullscreen != Default::default() {
+            (MainThreadMode::Fullscreen, MainThreadDispatch::Window(WindowSubclass::new(search.fullscreen, subclass_proc, 1, 0)?))
+        }
+        else if search.console != Default::default() {
+            (MainThreadMode::Console, MainThreadDispatch::Window(WindowSubclass::new(search.console, subclass_proc, 1, 0)?))
+        }
+        else {
+            // The hook can only be installed once symbols are available, see LCTwitch::new.
+            (MainThreadMode::Headless, MainThreadDispatch::Hook(None))
+        };
+
         Ok(LCTwitchMainThread{
             handle,
-            main_window_subclass: WindowSubclass::new(arguments.1, subclass_proc, 1, 0)?
+            mode,
+            dispatch
         })
     }
+
+    pub fn mode(&self) -> MainThreadMode {
+        self.mode
+    }
 }
 
 pub struct LCTwitch {
     main_thread_struct: LCTwitchMainThread,
     log: FnLog,
     config: Config,
-    script: Script
+    script: Result<Script, String>,
+    events: EventBus,
+    scheduler: Scheduler,
+    actions: ActionsFile,
+    automation: Automation,
+    holding_queue: HoldingQueue,
+    approvals: ApprovalQueue,
+    policy: PolicyFile,
+    natives: Result<Natives, String>,
+    game_hooks: Result<GameHooks, String>,
+    control: Result<Control, String>,
+    relay: Option<Relay>,
+    replay: Result<ReplayAnnotations, String>,
+    twitch: Twitch,
+    kv: Arc<KeyValueStore>,
+    audit: AuditLog,
+    kill_switch: KillSwitch,
+    helix: Helix
 }
 
 impl LCTwitch {
-    pub fn new(main_thread_struct: LCTwitchMainThread) -> Result<LCTwitch, Box<dyn std::error::Error>> {
-        unsafe {
-            SymSetOptions(SYMOPT_UNDNAME | SYMOPT_DEFERRED_LOADS | SYMOPT_LOAD_ANYTHING);
-            if !SymInitialize(GetCurrentProcess(), None, true).as_bool() {
-                return Err(windows::core::Error::from_win32().into());
-            }
-        }
-
+    pub fn new(mut main_thread_struct: LCTwitchMainThread) -> Result<LCTwitch, Box<dyn std::error::Error>> {
         let clonk_handle = unsafe { GetModuleHandleW(None)? };
         let clonk_module = Module::try_from(clonk_handle)?;
+        let clonk_image = unsafe { Image::from_base(clonk_handle.0 as *const u8)? };
+
+        let config = Config::new()?;
+        let clonk_path = Path::new(clonk_module.path().to_str()?);
+
+        let signature_directory = config.signature_directory()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| clonk_path.with_file_name("LCTwitch.signatures"));
 
-        let log = find_function::<FnLog>(&clonk_module, c_str!("Log")).ok_or("Failed to find Log")?;
+        // Signatures are only needed if the build has no symbols, so a broken file is only reported.
+        let (signatures, signature_error) = match SignatureFile::load(&signature_directory, &clonk_image) {
+            Ok(signatures) => (signatures, None),
+            Err(e) => (SignatureFile::default(), Some(e))
+        };
+
+        let symbols = Symbols::new(&clonk_module, &clonk_image, signatures);
+
+        let log = symbols.function::<FnLog>(c_str!("Log"))?;
         log(c_str!("Hello from Rust").as_ptr());
 
-        let script = Script::new(&clonk_module)?;
+        if let Some(e) = signature_error {
+            log(CString::new(format!("LCTwitch: {}", e))?.as_ptr());
+        }
+
+        if let MainThreadDispatch::Hook(hook) = &mut main_thread_struct.dispatch {
+            let execute = symbols.function::<FnGameExecute>(c_str!("C4Game::Execute"))?;
+
+            let application = match symbols.function::<FnApplicationExecute>(c_str!("C4Application::Execute")) {
+                Ok(execute) => Some(Detour::new(execute, application_execute as FnApplicationExecute, &APPLICATION_EXECUTE)?),
+                Err(e) => {
+                    log(CString::new(format!("LCTwitch: Main thread callbacks only run during rounds: {}", e))?.as_ptr());
+                    None
+                }
+            };
+
+            *hook = Some(MainThreadHooks {
+                _game_execute: Detour::new(execute, game_execute as FnGameExecute, &GAME_EXECUTE)?,
+                _application_execute: application
+            });
+        }
+
+        log(match main_thread_struct.mode {
+            MainThreadMode::Fullscreen => c_str!("LCTwitch: Running in fullscreen mode"),
+            MainThreadMode::Console => c_str!("LCTwitch: Running in editor mode"),
+            MainThreadMode::Headless => c_str!("LCTwitch: No game window found, running in headless mode")
+        }.as_ptr());
+
+        let snapshot_path = config.layout_snapshot_file()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| clonk_path.with_file_name("LCTwitch.layouts.json"));
+
+        let layout = Layout::load(&clonk_image, clonk_path.with_extension("pdb"), &snapshot_path);
+
+        // An unknown build only disables scripting, the server still reports why.
+        let script = Script::new(&symbols, &layout).map_err(|e| e.to_string());
+        if let Err(e) = &script {
+            log(CString::new(format!("LCTwitch: Scripting disabled: {}", e))?.as_ptr());
+        }
+
+        let kv = Arc::new(KeyValueStore::new(config.kv_file().map(Path::to_path_buf))?);
+
+        let natives = match &script {
+            Ok(script) => Natives::new(&symbols, &layout, script, kv.clone()).map_err(|e| e.to_string()),
+            Err(e) => Err(e.clone())
+        };
+
+        if let (Ok(_), Err(e)) = (&script, &natives) {
+            log(CString::new(format!("LCTwitch: Native functions disabled: {}", e))?.as_ptr());
+        }
+
+        let control = Control::new(&symbols, &layout).map_err(|e| e.to_string());
+        if let Err(e) = &control {
+            log(CString::new(format!("LCTwitch: Player controls disabled: {}", e))?.as_ptr());
+        }
+
+        // Without the hooks, only round starts and ends are reported.
+        let game_hooks = GameHooks::new(&symbols, &layout).map_err(|e| e.to_string());
+        if let Err(e) = &game_hooks {
+            log(CString::new(format!("LCTwitch: Game events disabled: {}", e))?.as_ptr());
+        }
+
+        let replay = ReplayAnnotations::new(&layout).map_err(|e| e.to_string());
+        if let Err(e) = &replay {
+            log(CString::new(format!("LCTwitch: Replay annotations disabled: {}", e))?.as_ptr());
+        }
+
+        // Only after the last lookup, so that the next start finds everything in the snapshot and needs no PDB.
+        if script.is_ok() {
+            if let Err(e) = layout.save(&snapshot_path) {
+                log(CString::new(format!("LCTwitch: Could not write {}: {}", snapshot_path.display(), e))?.as_ptr());
+            }
+        }
+
+        let scheduler = Scheduler::new(config.schedule_file().map(Path::to_path_buf))?;
+
+        let actions_path = config.actions_file()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| clonk_path.with_file_name("LCTwitch.actions.json"));
+
+        let actions = ActionsFile::load(&actions_path).unwrap_or_else(|e| {
+            let _ = CString::new(format!("LCTwitch: Actions disabled: {}", e)).map(|message| log(message.as_ptr()));
+            ActionsFile::default()
+        });
+
+        let policy_path = config.policy_file()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| clonk_path.with_file_name("LCTwitch.policy.json"));
+
+        // Unlike a broken action catalog, a broken policy must not silently allow everything.
+        let policy = PolicyFile::load(&policy_path)?;
+
+        let relay = Relay::new(&config)?;
+
+        let audit_path = config.audit_file()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| clonk_path.with_file_name("LCTwitch.audit.jsonl"));
+
+        let audit = AuditLog::new(audit_path, config.audit_max_size() as u64, config.audit_files());
 
         Ok(LCTwitch {
             main_thread_struct,
             log,
-            config: Config::new()?,
-            script
+            config,
+            script,
+            events: EventBus::new(),
+            scheduler,
+            actions,
+            automation: Automation::new(),
+            holding_queue: HoldingQueue::new(),
+            approvals: ApprovalQueue::new(),
+            policy,
+            natives,
+            game_hooks,
+            control,
+            relay,
+            replay,
+            twitch: Twitch::new(),
+            kv,
+            audit,
+            kill_switch: KillSwitch::new(),
+            helix: Helix::new()
         })
     }
 
@@ -143,21 +410,121 @@ impl LCTwitch {
         (self.log)(message.as_ptr());
     }
 
+    // The engine's log is not thread-safe, so background tasks log through the main thread.
+    pub fn log_in_main_thread(self: &Arc<Self>, message: String) {
+        let instance = self.clone();
+        self.run_in_main_thread(move || {
+            let _ = instance.log(&message);
+        });
+    }
+
     pub fn run_in_main_thread<F: FnOnce() + Send>(&self, op: F) {
-        let window = self.main_thread_struct.main_window_subclass.window();
-        let ptr = Box::new(op) as Box<dyn FnOnce() -> ()>;
+        match &self.main_thread_struct.dispatch {
+            MainThreadDispatch::Window(subclass) => {
+                let window = subclass.window();
+                let ptr = Box::new(op) as Box<dyn FnOnce() -> ()>;
+
+                unsafe {
+                    let raw = Box::into_raw(ptr);
+                    let fat_pointer = std::mem::transmute::<_, (WPARAM, LPARAM)>(raw);
+
+                    PostMessageA(window, WM_LCTWITCH_CALLBACK, fat_pointer.0, fat_pointer.1);
+                }
+            },
+            MainThreadDispatch::Hook(_) => {
+                let ptr = Box::new(op) as Box<dyn FnOnce() + Send + '_>;
+                let ptr = unsafe { std::mem::transmute::<_, Box<dyn FnOnce() + Send + 'static>>(ptr) };
+                MAIN_THREAD_QUEUE.lock().unwrap().push(ptr);
+            }
+        }
+    }
 
-        unsafe {
-            let raw = Box::into_raw(ptr);
-            let fat_pointer = std::mem::transmute::<_, (WPARAM, LPARAM)>(raw);
+    pub fn main_thread_mode(&self) -> MainThreadMode {
+        self.main_thread_struct.mode
+    }
 
-            PostMessageA(window, WM_LCTWITCH_CALLBACK, fat_pointer.0, fat_pointer.1);
+    pub fn main_window(&self) -> Option<HWND> {
+        match &self.main_thread_struct.dispatch {
+            MainThreadDispatch::Window(subclass) => Some(subclass.window()),
+            MainThreadDispatch::Hook(_) => None
         }
     }
 
     pub fn config(&self) -> &Config {
         &self.config
     }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    pub fn actions(&self) -> &ActionsFile {
+        &self.actions
+    }
+
+    pub fn automation(&self) -> &Automation {
+        &self.automation
+    }
+
+    pub fn holding_queue(&self) -> &HoldingQueue {
+        &self.holding_queue
+    }
+
+    pub fn approvals(&self) -> &ApprovalQueue {
+        &self.approvals
+    }
+
+    pub fn policy(&self) -> &PolicyFile {
+        &self.policy
+    }
+
+    pub fn script(&self) -> Result<&Script, http::Error> {
+        self.script.as_ref().map_err(|e| http::Error { code: http::ErrorCode::IncompatibleBuild, message: e.clone() })
+    }
+
+    pub fn natives(&self) -> Result<&Natives, http::Error> {
+        self.natives.as_ref().map_err(|e| http::Error { code: http::ErrorCode::IncompatibleBuild, message: e.clone() })
+    }
+
+    pub fn game_hooks(&self) -> Result<&GameHooks, http::Error> {
+        self.game_hooks.as_ref().map_err(|e| http::Error { code: http::ErrorCode::IncompatibleBuild, message: e.clone() })
+    }
+
+    pub fn control(&self) -> Result<&Control, http::Error> {
+        self.control.as_ref().map_err(|e| http::Error { code: http::ErrorCode::IncompatibleBuild, message: e.clone() })
+    }
+
+    pub fn relay(&self) -> Option<&Relay> {
+        self.relay.as_ref()
+    }
+
+    pub fn replay(&self) -> Result<&ReplayAnnotations, http::Error> {
+        self.replay.as_ref().map_err(|e| http::Error { code: http::ErrorCode::IncompatibleBuild, message: e.clone() })
+    }
+
+    pub fn twitch(&self) -> &Twitch {
+        &self.twitch
+    }
+
+    pub fn kv(&self) -> &KeyValueStore {
+        &self.kv
+    }
+
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub fn kill_switch(&self) -> &KillSwitch {
+        &self.kill_switch
+    }
+
+    pub fn helix(&self) -> &Helix {
+        &self.helix
+    }
 }
 
 impl Drop for LCTwitch {
@@ -187,6 +554,17 @@ pub async fn main(main_thread: LCTwitchMainThread) -> Result<(), Box<dyn Error>>
         tx.send(()).unwrap();
     });
 
+    tokio::spawn(scheduler::run(twitch.clone()));
+    tokio::spawn(automation::run(twitch.clone()));
+    tokio::spawn(queue::run(twitch.clone()));
+    tokio::spawn(natives::run(twitch.clone()));
+    tokio::spawn(twitch::run(twitch.clone()));
+    tokio::spawn(twitch::announce(twitch.clone()));
+    tokio::spawn(hooks::run(twitch.clone()));
+    tokio::spawn(replay::run(twitch.clone()));
+    tokio::spawn(killswitch::run(twitch.clone()));
+    tokio::spawn(helix::run(twitch.clone()));
+
     crate::http::run_server(twitch.clone(), rx).await;
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,313 @@
+use std::collections::BTreeSet;
+
+use crate::http::{Error, ErrorCode};
+
+const KEYWORDS: &[&str] = &[
+    "if", "else", "while", "for", "in", "do", "return", "break", "continue", "var", "local", "static", "global", "const",
+    "func", "public", "private", "protected", "new", "this", "true", "false", "nil", "par", "goto", "inherited", "_inherited",
+    "any", "int", "bool", "string", "object", "array", "map", "id", "dword", "reference"
+];
+
+const LOOP_KEYWORDS: &[&str] = &["while", "for", "goto"];
+
+#[derive(Clone, Copy)]
+enum DynamicCall {
+    // The argument at this index is the name of the function that gets called.
+    Function(usize),
+    // The argument at this index is a script that gets executed.
+    Script(usize)
+}
+
+// Engine functions that call other functions by name, which would otherwise bypass the allow and deny lists.
+const DYNAMIC_CALLS: &[(&str, DynamicCall)] = &[
+    ("Call", DynamicCall::Function(0)),
+    ("GameCall", DynamicCall::Function(0)),
+    ("GameCallEx", DynamicCall::Function(0)),
+    ("PrivateCall", DynamicCall::Function(1)),
+    ("ProtectedCall", DynamicCall::Function(1)),
+    ("ObjectCall", DynamicCall::Function(1)),
+    ("DefinitionCall", DynamicCall::Function(1)),
+    ("ScheduleCall", DynamicCall::Function(1)),
+    ("Schedule", DynamicCall::Script(0)),
+    ("eval", DynamicCall::Script(0))
+];
+
+// Longest first, so that the first match is the longest one.
+const OPERATORS: &[&str] = &[
+    "===", "!==", "<<=", ">>=", "??=", "->~", "...",
+    "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "**", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "->", "::", "??",
+    "(", ")", "[", "]", "{", "}", ",", ";", ":", "?", "!", "~", "*", "/", "%", "+", "-", "<", ">", "&", "^", "|", "=", "."
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Identifier,
+    Keyword,
+    Number,
+    String,
+    Operator
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    // Byte offset into the script.
+    pub position: usize
+}
+
+fn error(code: ErrorCode, script: &str, position: usize, message: &str) -> Error {
+    let before = &script[..position];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rfind('\n').map_or(before, |index| &before[index + 1..]).chars().count() + 1;
+
+    Error {
+        code,
+        message: format!("line {}, column {}: {}", line, column, message)
+    }
+}
+
+fn parse_error(script: &str, position: usize, message: &str) -> Error {
+    error(ErrorCode::ScriptParseError, script, position, message)
+}
+
+// Numbers and IDs both start with a digit in some cases, e.g. 0x1F and 1ABC.
+fn is_number_or_id(text: &str) -> bool {
+    text.bytes().all(|c| c.is_ascii_digit())
+        || text.strip_prefix("0x").map_or(false, |hex| !hex.is_empty() && hex.bytes().all(|c| c.is_ascii_hexdigit()))
+        || (text.len() == 4 && text.bytes().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_'))
+}
+
+// Strips the quotes of a string token and undoes its escapes.
+pub fn unescape(string: &str) -> String {
+    let mut result = String::with_capacity(string.len());
+    let mut chars = string[1..string.len() - 1].chars();
+
+    while let Some(c) = chars.next() {
+        result.extend(if c == '\\' { chars.next() } else { Some(c) });
+    }
+
+    result
+}
+
+// The tokens of the argument at the given index of the call whose opening bracket is at open.
+fn argument<'a, 'b>(tokens: &'b [Token<'a>], open: usize, index: usize) -> Option<&'b [Token<'a>]> {
+    let mut depth = 0;
+    let mut current = 0;
+    let mut start = open + 1;
+
+    for (position, token) in tokens.iter().enumerate().skip(open + 1) {
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" if depth > 0 => depth -= 1,
+            "," | ")" if depth == 0 => {
+                if current == index {
+                    return Some(&tokens[start..position]);
+                }
+                else if token.text == ")" {
+                    return None;
+                }
+
+                current += 1;
+                start = position + 1;
+            },
+            _ => {}
+        }
+    }
+
+    None
+}
+
+pub fn tokenize(script: &str) -> Result<Vec<Token<'_>>, Error> {
+    let bytes = script.as_bytes();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let rest = &script[position..];
+        let c = bytes[position];
+
+        if c.is_ascii_whitespace() {
+            position += 1;
+            continue;
+        }
+        else if rest.starts_with("//") {
+            position += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        }
+        else if let Some(comment) = rest.strip_prefix("/*") {
+            position += comment.find("*/").ok_or_else(|| parse_error(script, position, "Unterminated comment"))? + 4;
+            continue;
+        }
+
+        let (kind, length) = if c == b'"' {
+            let mut end = 1;
+            while end < rest.len() && bytes[position + end] != b'"' {
+                end += if bytes[position + end] == b'\\' { 2 } else { 1 };
+            }
+
+            if end >= rest.len() {
+                return Err(parse_error(script, position, "Unterminated string"));
+            }
+
+            (TokenKind::String, end + 1)
+        }
+        else if c.is_ascii_alphanumeric() || c == b'_' {
+            let length = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let text = &rest[..length];
+
+            if c.is_ascii_digit() {
+                if !is_number_or_id(text) {
+                    return Err(parse_error(script, position, &format!("Invalid number {}", text)));
+                }
+
+                (TokenKind::Number, length)
+            }
+            else if KEYWORDS.contains(&text) {
+                (TokenKind::Keyword, length)
+            }
+            else {
+                (TokenKind::Identifier, length)
+            }
+        }
+        else {
+            let operator = OPERATORS.iter()
+                .find(|operator| rest.starts_with(*operator))
+                .ok_or_else(|| parse_error(script, position, &format!("Unexpected character {}", rest.chars().next().unwrap_or_default())))?;
+
+            (TokenKind::Operator, operator.len())
+        };
+
+        tokens.push(Token { kind, text: &rest[..length], position });
+        position += length;
+    }
+
+    Ok(tokens)
+}
+
+// Catches the mistakes that are cheap to find without a full parser: unbalanced brackets, control statements
+// without a condition and two operands without an operator between them.
+fn check_syntax(script: &str, tokens: &[Token]) -> Result<(), Error> {
+    if tokens.is_empty() {
+        return Err(parse_error(script, 0, "Script is empty"));
+    }
+
+    let mut open = Vec::<&Token>::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        let next = tokens.get(index + 1);
+
+        match (token.kind, token.text) {
+            (TokenKind::Operator, "(" | "[" | "{") => open.push(token),
+            (TokenKind::Operator, closing @ (")" | "]" | "}")) => {
+                let expected = match open.pop().map(|token| token.text) {
+                    Some("(") => ")",
+                    Some("[") => "]",
+                    Some("{") => "}",
+                    _ => return Err(parse_error(script, token.position, &format!("Unexpected {}", closing)))
+                };
+
+                if closing != expected {
+                    return Err(parse_error(script, token.position, &format!("Expected {}, found {}", expected, closing)));
+                }
+            },
+            (TokenKind::Keyword, "if" | "while" | "for") if next.map_or(true, |next| next.text != "(") => {
+                return Err(parse_error(script, token.position, &format!("Expected ( after {}", token.text)));
+            },
+            (TokenKind::Identifier | TokenKind::Number | TokenKind::String, _) => {
+                if let Some(next) = next.filter(|next| matches!(next.kind, TokenKind::Identifier | TokenKind::Number | TokenKind::String)) {
+                    return Err(parse_error(script, next.position, "Missing operator"));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    match open.pop() {
+        Some(token) => Err(parse_error(script, token.position, &format!("Unclosed {}", token.text))),
+        None => Ok(())
+    }
+}
+
+// The checks every script has to pass before it is sent to the engine. Scripts that are denied by them never reach
+// a control packet, and thus never end up in the replay either.
+#[derive(Clone, Debug, Default)]
+pub struct LintRules {
+    pub max_length: Option<usize>,
+    pub max_loops: Option<usize>,
+    // If set, no other functions may be called.
+    pub allowed_functions: Option<BTreeSet<String>>,
+    pub denied_functions: BTreeSet<String>
+}
+
+impl LintRules {
+    pub fn check(&self, script: &str) -> Result<(), Error> {
+        if let Some(max_length) = self.max_length.filter(|max_length| script.len() > *max_length) {
+            return Err(error(ErrorCode::ScriptRejected, script, 0, &format!("Script is longer than {} bytes", max_length)));
+        }
+
+        let tokens = tokenize(script)?;
+        check_syntax(script, &tokens)?;
+
+        let mut loops = 0;
+
+        for (index, token) in tokens.iter().enumerate() {
+            if token.kind == TokenKind::Keyword && LOOP_KEYWORDS.contains(&token.text) {
+                loops += 1;
+
+                if let Some(max_loops) = self.max_loops.filter(|max_loops| loops > *max_loops) {
+                    return Err(error(ErrorCode::ScriptRejected, script, token.position, &format!("Script contains more than {} loops", max_loops)));
+                }
+            }
+
+            // Calls through obj->Function() are checked as well, since engine functions can be called that way too.
+            let is_call = token.kind == TokenKind::Identifier && tokens.get(index + 1).map_or(false, |next| next.text == "(");
+            if !is_call {
+                continue;
+            }
+
+            if !self.is_allowed(token.text) {
+                return Err(error(ErrorCode::ScriptRejected, script, token.position, &format!("{} must not be called", token.text)));
+            }
+
+            if let Some((_, call)) = DYNAMIC_CALLS.iter().find(|(name, _)| *name == token.text).filter(|_| self.restricts_calls()) {
+                self.check_dynamic_call(script, &tokens, index, *call)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn restricts_calls(&self) -> bool {
+        self.allowed_functions.is_some() || !self.denied_functions.is_empty()
+    }
+
+    fn is_allowed(&self, function: &str) -> bool {
+        self.allowed_functions.as_ref().map_or(true, |allowed| allowed.contains(function))
+            && !self.denied_functions.contains(function)
+    }
+
+    // Functions called by name are only allowed if the name is a string literal that passes the lists itself.
+    fn check_dynamic_call(&self, script: &str, tokens: &[Token], index: usize, call: DynamicCall) -> Result<(), Error> {
+        let token = &tokens[index];
+        let (argument_index, kind) = match call {
+            DynamicCall::Function(argument_index) => (argument_index, "function name"),
+            DynamicCall::Script(argument_index) => (argument_index, "script")
+        };
+
+        let value = match argument(tokens, index + 1, argument_index) {
+            Some([Token { kind: TokenKind::String, text, .. }]) => unescape(text),
+            _ => return Err(error(ErrorCode::ScriptRejected, script, token.position, &format!("{} must be called with a fixed {}", token.text, kind)))
+        };
+
+        match call {
+            // A leading ~ only makes the call fail silently if the function does not exist.
+            DynamicCall::Function(_) if !self.is_allowed(value.trim_start_matches('~')) => {
+                Err(error(ErrorCode::ScriptRejected, script, token.position, &format!("{} must not be called", value.trim_start_matches('~'))))
+            },
+            DynamicCall::Function(_) => Ok(()),
+            DynamicCall::Script(_) => self.check(&value)
+                .map_err(|e| error(ErrorCode::ScriptRejected, script, token.position, &format!("{}: {}", token.text, e.message)))
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,327 @@
+use std::{error::Error, ffi::{CString, c_char, c_void}, sync::{Arc, Mutex, atomic::{AtomicPtr, AtomicUsize, Ordering}}};
+
+use byte_strings::c_str;
+use cpp::*;
+use serde_json::Value;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::{LCTwitch, args, detour::{self, Detour}, events::Event, kv::KeyValueStore, layout::Layout, policy::GameMode, resolver::Member, script::{C4Value, Script, ValueFunctions}, signature::Symbols};
+
+type C4AulScriptEngine = c_void;
+type C4AulFunc = c_void;
+type C4AulContext = c_void;
+
+type FnInitFunctionMap = extern "win64" fn(*mut C4AulScriptEngine);
+
+// C4AUL_MAX_Par; the engine always passes this many parameters, filling the rest with nil.
+const MAX_PARAMETERS: usize = 10;
+
+// C4V_Any for every parameter, so that the engine does not convert anything.
+static PARAMETER_TYPES: [i32; MAX_PARAMETERS] = [0; MAX_PARAMETERS];
+
+static INIT_FUNCTION_MAP: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static REGISTRY: AtomicPtr<Registry> = AtomicPtr::new(std::ptr::null_mut());
+// The engine's size of a C4Value. Kept when the registry is gone, since registered functions still return nil then.
+static VALUE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NativeKind {
+    Emit,
+    Say,
+    Get,
+    Set
+}
+
+impl NativeKind {
+    const ALL: [NativeKind; 4] = [NativeKind::Emit, NativeKind::Say, NativeKind::Get, NativeKind::Set];
+
+    fn name(self) -> &'static str {
+        match self {
+            NativeKind::Emit => "LCTwitch_Emit",
+            NativeKind::Say => "LCTwitch_Say",
+            NativeKind::Get => "LCTwitch_Get",
+            NativeKind::Set => "LCTwitch_Set"
+        }
+    }
+
+    fn parameter_count(self) -> i32 {
+        match self {
+            NativeKind::Emit | NativeKind::Set => 2,
+            NativeKind::Say | NativeKind::Get => 1
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum NativeCall {
+    Emit {
+        name: String,
+        data: Value
+    },
+    Say {
+        message: String
+    },
+    Set {
+        key: String,
+        value: Value
+    }
+}
+
+struct Registry {
+    constructor: extern "win64" fn(*mut C4AulFunc, *mut C4AulScriptEngine, *const c_char, bool),
+    func_size: usize,
+    // One copy of the C4AulFunc vtable per native, with the native's kind stored after the last entry.
+    vtables: Vec<Box<[*const c_void]>>,
+    vtable_entries: usize,
+    values: ValueFunctions,
+    store: Arc<KeyValueStore>,
+    calls: UnboundedSender<NativeCall>,
+    control_mode: Member<i32>,
+    control_record: Member<*mut c_void>,
+    network_state: Member<i32>
+}
+
+unsafe impl Send for Registry {}
+unsafe impl Sync for Registry {}
+
+// Registers engine functions implemented here, so that scenario scripts can talk to LCTwitch.
+pub struct Natives {
+    _hook: Detour<FnInitFunctionMap, FnInitFunctionMap>,
+    registry: Box<Registry>,
+    calls: Mutex<Option<UnboundedReceiver<NativeCall>>>
+}
+
+unsafe impl Send for Natives {}
+unsafe impl Sync for Natives {}
+
+impl Natives {
+    pub fn new(symbols: &Symbols, layout: &Layout, script: &Script, store: Arc<KeyValueStore>) -> Result<Natives, Box<dyn Error>> {
+        let vtable_entries = layout.vtable_entries("C4AulFunc")?;
+        // The first Exec overload is the one the engine calls with the evaluated parameters.
+        let exec = layout.virtual_method_index("C4AulFunc", "Exec")?;
+        let get_public = layout.virtual_method_index("C4AulFunc", "GetPublic")?;
+        let get_par_count = layout.virtual_method_index("C4AulFunc", "GetParCount")?;
+        let get_par_type = layout.virtual_method_index("C4AulFunc", "GetParType")?;
+
+        let original_vtable = match layout.vtable("C4AulFunc") {
+            Ok(vtable) => vtable,
+            Err(_) => symbols.function(c_str!("C4AulFunc::`vftable'"))?
+        };
+
+        let vtables = NativeKind::ALL.iter()
+            .map(|kind| {
+                let mut vtable = vec![std::ptr::null(); vtable_entries + 2].into_boxed_slice();
+                unsafe {
+                    std::ptr::copy_nonoverlapping(original_vtable.offset(-1), vtable.as_mut_ptr(), vtable_entries + 1);
+                }
+
+                vtable[1 + exec] = native_exec as *const c_void;
+                vtable[1 + get_public] = native_get_public as *const c_void;
+                vtable[1 + get_par_count] = native_get_par_count as *const c_void;
+                vtable[1 + get_par_type] = native_get_par_type as *const c_void;
+                vtable[1 + vtable_entries] = *kind as usize as *const c_void;
+                vtable
+            })
+            .collect();
+
+        VALUE_SIZE.store(layout.type_size("C4Value")?, Ordering::SeqCst);
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let registry = Box::new(Registry {
+            constructor: symbols.function(c_str!("C4AulFunc::C4AulFunc"))?,
+            func_size: layout.type_size("C4AulFunc")?,
+            vtables,
+            vtable_entries,
+            values: script.value_functions(),
+            store,
+            calls: sender,
+            control_mode: layout.resolve::<i32>("Game.Control.eMode")?,
+            control_record: layout.resolve::<*mut c_void>("Game.Control.pRecord")?,
+            network_state: layout.resolve::<i32>("Game.Network.Status.eState")?
+        });
+
+        REGISTRY.store(registry.as_ref() as *const Registry as *mut Registry, Ordering::SeqCst);
+
+        // The engine deletes all functions when a round ends and registers them again for the next one.
+        let init_function_map = symbols.function::<FnInitFunctionMap>(c_str!("InitFunctionMap"))?;
+        let hook = Detour::new(init_function_map, init_function_map_hook as FnInitFunctionMap, &INIT_FUNCTION_MAP)?;
+
+        Ok(Natives {
+            _hook: hook,
+            registry,
+            calls: Mutex::new(Some(receiver))
+        })
+    }
+}
+
+impl Drop for Natives {
+    fn drop(&mut self) {
+        // Functions that were already registered stay in the engine and keep using their vtables, but return nil
+        // without the registry.
+        REGISTRY.store(std::ptr::null_mut(), Ordering::SeqCst);
+
+        for vtable in std::mem::take(&mut self.registry.vtables) {
+            Box::leak(vtable);
+        }
+    }
+}
+
+impl Registry {
+    unsafe fn register(&self, script_engine: *mut C4AulScriptEngine) {
+        for (kind, vtable) in NativeKind::ALL.iter().zip(&self.vtables) {
+            let func_size = self.func_size;
+            let func = cpp!(unsafe [func_size as "std::size_t"] -> *mut c_void as "void *" {
+                return ::operator new(func_size, std::nothrow);
+            });
+
+            if func.is_null() {
+                return;
+            }
+
+            func.write_bytes(0, func_size);
+
+            // The constructor adds the function to the engine, which owns it from then on.
+            let name = CString::new(kind.name()).unwrap();
+            (self.constructor)(func, script_engine, name.as_ptr(), true);
+            (func as *mut *const *const c_void).write(vtable.as_ptr().add(1));
+        }
+    }
+
+    unsafe fn kind(&self, func: *const C4AulFunc) -> NativeKind {
+        let vtable = *(func as *const *const *const c_void);
+        NativeKind::ALL[vtable.add(self.vtable_entries).read() as usize]
+    }
+
+    unsafe fn parameter(&self, parameters: *const C4Value, index: usize) -> Value {
+        args::from_literal(&self.values.data_string(parameters.add(index)))
+    }
+
+    // Whether other clients of a network game or a replay of the recording run the same scripts. The values only exist
+    // in this process, so scripts that depend on them would desynchronize there.
+    unsafe fn is_shared(&self) -> bool {
+        self.control_mode.read() == Some(3)
+            || self.network_state.read().unwrap_or(0) != 0
+            || self.control_record.read().map_or(false, |record| !record.is_null())
+    }
+
+    unsafe fn call(&self, kind: NativeKind, parameters: *const C4Value) -> Value {
+        let text = |value: Value| match value {
+            Value::String(text) => text,
+            value => value.to_string()
+        };
+
+        match kind {
+            NativeKind::Emit => {
+                let _ = self.calls.send(NativeCall::Emit {
+                    name: text(self.parameter(parameters, 0)),
+                    data: self.parameter(parameters, 1)
+                });
+
+                Value::Null
+            },
+            NativeKind::Say => {
+                let _ = self.calls.send(NativeCall::Say {
+                    message: text(self.parameter(parameters, 0))
+                });
+
+                Value::Null
+            },
+            NativeKind::Get if self.is_shared() => Value::Null,
+            NativeKind::Set if self.is_shared() => Value::Bool(false),
+            NativeKind::Get => self.store.get(&text(self.parameter(parameters, 0))).unwrap_or(Value::Null),
+            // The value is visible to scripts right away, saving and announcing it happens in the background.
+            NativeKind::Set => {
+                let key = text(self.parameter(parameters, 0));
+                let value = self.parameter(parameters, 1);
+
+                match self.store.set(&key, value.clone()) {
+                    Ok(()) => {
+                        let _ = self.calls.send(NativeCall::Set { key, value });
+                        Value::Bool(true)
+                    },
+                    Err(_) => Value::Bool(false)
+                }
+            }
+        }
+    }
+}
+
+extern "win64" fn init_function_map_hook(script_engine: *mut C4AulScriptEngine) {
+    unsafe {
+        detour::original::<FnInitFunctionMap>(&INIT_FUNCTION_MAP)(script_engine);
+
+        if let Some(registry) = REGISTRY.load(Ordering::SeqCst).as_ref() {
+            registry.register(script_engine);
+        }
+    }
+}
+
+// C4Value Exec(C4AulContext *pCallerCtx, const C4Value pPars[], bool fPassErrors). Like every member function
+// returning a class, the result is written to a buffer the caller passes after this.
+extern "win64" fn native_exec(func: *mut C4AulFunc, result: *mut C4Value, _context: *mut C4AulContext, parameters: *const C4Value, _pass_errors: bool) -> *mut C4Value {
+    unsafe {
+        let registry = match REGISTRY.load(Ordering::SeqCst).as_ref() {
+            Some(registry) => registry,
+            None => {
+                // An all-zero C4Value is nil.
+                (result as *mut u8).write_bytes(0, VALUE_SIZE.load(Ordering::SeqCst));
+                return result;
+            }
+        };
+
+        let value = registry.call(registry.kind(func), parameters);
+
+        // Values that have no literal, e.g. floats, are returned as nil.
+        let literal = args::to_literal(&value, "result").ok()
+            .and_then(|literal| CString::new(literal).ok())
+            .unwrap_or_else(|| CString::new("nil").unwrap());
+
+        registry.values.evaluate_literal(result, &literal);
+    }
+
+    result
+}
+
+extern "win64" fn native_get_public(_func: *mut C4AulFunc) -> bool {
+    true
+}
+
+extern "win64" fn native_get_par_count(func: *mut C4AulFunc) -> i32 {
+    unsafe {
+        REGISTRY.load(Ordering::SeqCst).as_ref().map_or(0, |registry| registry.kind(func).parameter_count())
+    }
+}
+
+extern "win64" fn native_get_par_type(_func: *mut C4AulFunc) -> *const i32 {
+    PARAMETER_TYPES.as_ptr()
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let mut calls = match instance.natives().ok().and_then(|natives| natives.calls.lock().unwrap().take()) {
+        Some(calls) => calls,
+        None => return
+    };
+
+    while let Some(call) = calls.recv().await {
+        // Every client of a network game and every replay runs the same scripts, but only the host speaks for the game.
+        let is_speaker = instance.script()
+            .map_or(false, |script| !script.game_state().modes.iter().any(|mode| matches!(mode, GameMode::Client | GameMode::Replay)));
+
+        match call {
+            NativeCall::Emit { .. } | NativeCall::Say { .. } if !is_speaker => {},
+            NativeCall::Emit { name, data } => instance.events().publish(Event::ScenarioEvent { name, data }),
+            NativeCall::Say { message } => {
+                instance.twitch().say(&message);
+                instance.events().publish(Event::ScenarioMessage { message });
+            },
+            NativeCall::Set { key, value } => {
+                if let Err(e) = instance.kv().save() {
+                    instance.log_in_main_thread(format!("LCTwitch: Could not save values: {}", e));
+                }
+
+                instance.events().publish(Event::ValueChanged { key, value });
+            }
+        }
+    }
+}
\ No newline at end of file
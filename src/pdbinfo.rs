@@ -0,0 +1,525 @@
+use std::{collections::HashMap, error::Error, fs::File, path::Path};
+
+use pdb::{FallibleIterator, PrimitiveKind, SymbolData, TypeData, TypeFinder, TypeIndex, PDB};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TypeRef {
+    Void,
+    Primitive { name: &'static str, size: usize },
+    Pointer { pointee: Box<TypeRef>, size: usize },
+    Named { name: String },
+    Enum { name: String, size: usize },
+    Array { element: Box<TypeRef>, size: usize },
+    Bitfield { underlying: Box<TypeRef>, position: u8, length: u8 },
+    Function,
+    Unknown
+}
+
+impl std::fmt::Display for TypeRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Void => write!(f, "void"),
+            Self::Primitive { name, .. } => write!(f, "{}", name),
+            Self::Pointer { pointee, .. } => write!(f, "{} *", pointee),
+            Self::Named { name } | Self::Enum { name, .. } => write!(f, "{}", name),
+            Self::Array { element, size } => write!(f, "{}[{} bytes]", element, size),
+            Self::Bitfield { underlying, position, length } => write!(f, "{} : {} @ {}", underlying, length, position),
+            Self::Function => write!(f, "<function>"),
+            Self::Unknown => write!(f, "<unknown>")
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemberLayout {
+    pub name: String,
+    pub offset: usize,
+    pub type_ref: TypeRef
+}
+
+#[derive(Clone, Debug)]
+pub struct BaseClassLayout {
+    pub name: String,
+    pub offset: usize
+}
+
+#[derive(Clone, Debug)]
+pub struct VirtualMethod {
+    pub name: String,
+    pub vtable_index: usize
+}
+
+#[derive(Clone, Debug)]
+pub struct TypeLayout {
+    pub name: String,
+    pub size: usize,
+    pub members: Vec<MemberLayout>,
+    pub base_classes: Vec<BaseClassLayout>,
+    // Only the virtual methods this type introduces; overrides reuse the slot of the base class.
+    pub virtual_methods: Vec<VirtualMethod>
+}
+
+// Section indices are 1-based, like in the image's section table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SymbolLocation {
+    pub section: u16,
+    pub offset: u32
+}
+
+impl std::fmt::Display for SymbolLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04X}:{:08X}", self.section, self.offset)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Global {
+    pub location: SymbolLocation,
+    pub type_ref: TypeRef
+}
+
+pub struct PdbInfo {
+    types: HashMap<String, TypeLayout>,
+    globals: HashMap<String, Global>,
+    functions: HashMap<String, SymbolLocation>,
+    publics: HashMap<String, SymbolLocation>
+}
+
+fn primitive(kind: PrimitiveKind) -> TypeRef {
+    let (name, size) = match kind {
+        PrimitiveKind::NoType | PrimitiveKind::Void => return TypeRef::Void,
+        PrimitiveKind::Char | PrimitiveKind::RChar => ("char", 1),
+        PrimitiveKind::UChar => ("unsigned char", 1),
+        PrimitiveKind::I8 => ("int8_t", 1),
+        PrimitiveKind::U8 => ("uint8_t", 1),
+        PrimitiveKind::Bool8 => ("bool", 1),
+        PrimitiveKind::WChar | PrimitiveKind::RChar16 => ("wchar_t", 2),
+        PrimitiveKind::Short | PrimitiveKind::I16 => ("short", 2),
+        PrimitiveKind::UShort | PrimitiveKind::U16 => ("unsigned short", 2),
+        PrimitiveKind::Bool16 => ("bool16", 2),
+        PrimitiveKind::RChar32 => ("char32_t", 4),
+        PrimitiveKind::Long => ("long", 4),
+        PrimitiveKind::ULong => ("unsigned long", 4),
+        PrimitiveKind::I32 => ("int", 4),
+        PrimitiveKind::U32 => ("unsigned int", 4),
+        PrimitiveKind::Bool32 => ("bool32", 4),
+        PrimitiveKind::HRESULT => ("HRESULT", 4),
+        PrimitiveKind::F32 | PrimitiveKind::F32PP => ("float", 4),
+        PrimitiveKind::Quad | PrimitiveKind::I64 => ("int64_t", 8),
+        PrimitiveKind::UQuad | PrimitiveKind::U64 => ("uint64_t", 8),
+        PrimitiveKind::Bool64 => ("bool64", 8),
+        PrimitiveKind::F64 => ("double", 8),
+        PrimitiveKind::F80 => ("long double", 10),
+        PrimitiveKind::Octa | PrimitiveKind::I128 => ("int128_t", 16),
+        PrimitiveKind::UOcta | PrimitiveKind::U128 => ("uint128_t", 16),
+        _ => return TypeRef::Unknown
+    };
+
+    TypeRef::Primitive { name, size }
+}
+
+fn resolve_type_ref(finder: &TypeFinder<'_>, index: TypeIndex) -> TypeRef {
+    let data = match finder.find(index).and_then(|item| item.parse()) {
+        Ok(data) => data,
+        Err(_) => return TypeRef::Unknown
+    };
+
+    match data {
+        TypeData::Primitive(primitive_type) => {
+            let pointee = primitive(primitive_type.kind);
+            match primitive_type.indirection {
+                None => pointee,
+                Some(_) => TypeRef::Pointer { pointee: Box::new(pointee), size: 8 }
+            }
+        },
+        TypeData::Pointer(pointer) => TypeRef::Pointer {
+            pointee: Box::new(resolve_type_ref(finder, pointer.underlying_type)),
+            size: pointer.attributes.size() as usize
+        },
+        TypeData::Modifier(modifier) => resolve_type_ref(finder, modifier.underlying_type),
+        TypeData::Class(class) => TypeRef::Named { name: class.name.to_string().into_owned() },
+        TypeData::Union(union) => TypeRef::Named { name: union.name.to_string().into_owned() },
+        TypeData::Enumeration(enumeration) => {
+            let size = match resolve_type_ref(finder, enumeration.underlying_type) {
+                TypeRef::Primitive { size, .. } => size,
+                _ => 4
+            };
+
+            TypeRef::Enum { name: enumeration.name.to_string().into_owned(), size }
+        },
+        TypeData::Array(array) => TypeRef::Array {
+            element: Box::new(resolve_type_ref(finder, array.element_type)),
+            size: array.dimensions.last().copied().unwrap_or_default() as usize
+        },
+        TypeData::Bitfield(bitfield) => TypeRef::Bitfield {
+            underlying: Box::new(resolve_type_ref(finder, bitfield.underlying_type)),
+            position: bitfield.position,
+            length: bitfield.length
+        },
+        TypeData::Procedure(_) | TypeData::MemberFunction(_) => TypeRef::Function,
+        _ => TypeRef::Unknown
+    }
+}
+
+fn collect_fields(finder: &TypeFinder<'_>, fields: Option<TypeIndex>, layout: &mut TypeLayout) -> Result<(), Box<dyn Error>> {
+    let mut next = fields;
+
+    while let Some(index) = next {
+        let field_list = match finder.find(index)?.parse()? {
+            TypeData::FieldList(field_list) => field_list,
+            _ => break
+        };
+
+        for field in field_list.fields {
+            match field {
+                TypeData::Member(member) => layout.members.push(MemberLayout {
+                    name: member.name.to_string().into_owned(),
+                    offset: member.offset as usize,
+                    type_ref: resolve_type_ref(finder, member.field_type)
+                }),
+                TypeData::BaseClass(base) => {
+                    if let TypeRef::Named { name } = resolve_type_ref(finder, base.base_class) {
+                        layout.base_classes.push(BaseClassLayout { name, offset: base.offset as usize });
+                    }
+                },
+                TypeData::Method(method) => {
+                    if let Some(vtable_offset) = method.vtable_offset {
+                        layout.virtual_methods.push(VirtualMethod {
+                            name: method.name.to_string().into_owned(),
+                            vtable_index: vtable_offset as usize / std::mem::size_of::<u64>()
+                        });
+                    }
+                },
+                TypeData::OverloadedMethod(overloaded) => {
+                    if let Ok(TypeData::MethodList(method_list)) = finder.find(overloaded.method_list).and_then(|item| item.parse()) {
+                        for vtable_offset in method_list.methods.iter().filter_map(|entry| entry.vtable_offset) {
+                            layout.virtual_methods.push(VirtualMethod {
+                                name: overloaded.name.to_string().into_owned(),
+                                vtable_index: vtable_offset as usize / std::mem::size_of::<u64>()
+                            });
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        next = field_list.continuation;
+    }
+
+    Ok(())
+}
+
+impl PdbInfo {
+    pub fn open(path: &Path) -> Result<PdbInfo, Box<dyn Error>> {
+        let mut pdb = PDB::open(File::open(path)?)?;
+
+        let type_information = pdb.type_information()?;
+        let mut finder = type_information.finder();
+        let mut type_iter = type_information.iter();
+        let mut classes = Vec::new();
+
+        while let Some(item) = type_iter.next()? {
+            finder.update(&type_iter);
+
+            match item.parse() {
+                Ok(TypeData::Class(class)) if !class.properties.forward_reference() => {
+                    classes.push((class.name.to_string().into_owned(), class.size as usize, class.fields));
+                },
+                Ok(TypeData::Union(union)) if !union.properties.forward_reference() => {
+                    classes.push((union.name.to_string().into_owned(), union.size as usize, Some(union.fields)));
+                },
+                _ => {}
+            }
+        }
+
+        let mut types = HashMap::new();
+
+        for (name, size, fields) in classes {
+            if types.contains_key(&name) {
+                continue;
+            }
+
+            let mut layout = TypeLayout {
+                name: name.clone(),
+                size,
+                members: Vec::new(),
+                base_classes: Vec::new(),
+                virtual_methods: Vec::new()
+            };
+
+            collect_fields(&finder, fields, &mut layout)?;
+            types.insert(name, layout);
+        }
+
+        let mut globals = HashMap::new();
+        let mut functions = HashMap::new();
+        let mut publics = HashMap::new();
+
+        let mut add_symbol = |symbol: SymbolData<'_>| {
+            match symbol {
+                SymbolData::Data(data) if data.global => {
+                    globals.entry(data.name.to_string().into_owned()).or_insert_with(|| Global {
+                        location: SymbolLocation { section: data.offset.section, offset: data.offset.offset },
+                        type_ref: resolve_type_ref(&finder, data.type_index)
+                    });
+                },
+                SymbolData::Procedure(procedure) => {
+                    functions.entry(procedure.name.to_string().into_owned())
+                        .or_insert(SymbolLocation { section: procedure.offset.section, offset: procedure.offset.offset });
+                },
+                SymbolData::Public(public) => {
+                    publics.entry(public.name.to_string().into_owned())
+                        .or_insert(SymbolLocation { section: public.offset.section, offset: public.offset.offset });
+                },
+                _ => {}
+            }
+        };
+
+        // Stripped or partially written PDBs may lack the global symbol stream.
+        if let Ok(global_symbols) = pdb.global_symbols() {
+            let mut symbols = global_symbols.iter();
+            while let Some(symbol) = symbols.next()? {
+                if let Ok(symbol) = symbol.parse() {
+                    add_symbol(symbol);
+                }
+            }
+        }
+
+        let debug_information = pdb.debug_information()?;
+        let mut modules = debug_information.modules()?;
+        while let Some(module) = modules.next()? {
+            let Some(module_info) = pdb.module_info(&module)? else {
+                continue;
+            };
+
+            let mut symbols = module_info.symbols()?;
+            while let Some(symbol) = symbols.next()? {
+                if let Ok(symbol) = symbol.parse() {
+                    add_symbol(symbol);
+                }
+            }
+        }
+
+        Ok(PdbInfo {
+            types,
+            globals,
+            functions,
+            publics
+        })
+    }
+
+    pub fn types(&self) -> impl Iterator<Item = &TypeLayout> {
+        self.types.values()
+    }
+
+    pub fn type_layout(&self, name: &str) -> Result<&TypeLayout, Box<dyn Error>> {
+        self.types.get(name).ok_or_else(|| format!("Type {} not found", name).into())
+    }
+
+    pub fn type_size(&self, name: &str) -> Result<usize, Box<dyn Error>> {
+        self.type_layout(name).map(|layout| layout.size)
+    }
+
+    pub fn size_of(&self, type_ref: &TypeRef) -> Option<usize> {
+        match type_ref {
+            TypeRef::Primitive { size, .. } | TypeRef::Pointer { size, .. } | TypeRef::Enum { size, .. } | TypeRef::Array { size, .. } => Some(*size),
+            TypeRef::Named { name } => self.types.get(name).map(|layout| layout.size),
+            TypeRef::Bitfield { underlying, .. } => self.size_of(underlying),
+            TypeRef::Void | TypeRef::Function | TypeRef::Unknown => None
+        }
+    }
+
+    // Searches the type and its base classes, returning the member's offset relative to the start of the type.
+    pub fn member(&self, type_name: &str, member_name: &str) -> Result<MemberLayout, Box<dyn Error>> {
+        self.find_member(type_name, member_name)
+            .ok_or_else(|| format!("Member {}::{} not found", type_name, member_name).into())
+    }
+
+    fn find_member(&self, type_name: &str, member_name: &str) -> Option<MemberLayout> {
+        let layout = self.types.get(type_name)?;
+
+        if let Some(member) = layout.members.iter().find(|member| member.name == member_name) {
+            return Some(member.clone());
+        }
+
+        layout.base_classes.iter().find_map(|base| {
+            self.find_member(&base.name, member_name).map(|member| MemberLayout {
+                offset: base.offset + member.offset,
+                ..member
+            })
+        })
+    }
+
+    pub fn member_offset(&self, type_name: &str, member_name: &str) -> Result<usize, Box<dyn Error>> {
+        self.member(type_name, member_name).map(|member| member.offset)
+    }
+
+    // Counts the entries of the primary vtable, i.e. the one at offset 0 shared with the first base class.
+    pub fn vtable_entries(&self, type_name: &str) -> Result<usize, Box<dyn Error>> {
+        let layout = self.type_layout(type_name)?;
+
+        let own = layout.virtual_methods.iter().map(|method| method.vtable_index + 1).max().unwrap_or_default();
+        let base = match layout.base_classes.iter().find(|base| base.offset == 0) {
+            Some(base) => self.vtable_entries(&base.name)?,
+            None => 0
+        };
+
+        Ok(own.max(base))
+    }
+
+    pub fn virtual_method_index(&self, type_name: &str, method_name: &str) -> Result<usize, Box<dyn Error>> {
+        let layout = self.type_layout(type_name)?;
+
+        if let Some(method) = layout.virtual_methods.iter().find(|method| method.name == method_name) {
+            return Ok(method.vtable_index);
+        }
+
+        layout.base_classes.iter()
+            .filter(|base| base.offset == 0)
+            .find_map(|base| self.virtual_method_index(&base.name, method_name).ok())
+            .ok_or_else(|| format!("Virtual method {}::{} not found", type_name, method_name).into())
+    }
+
+    pub fn global(&self, name: &str) -> Result<&Global, Box<dyn Error>> {
+        self.globals.get(name).ok_or_else(|| format!("Global {} not found", name).into())
+    }
+
+    pub fn function(&self, name: &str) -> Result<SymbolLocation, Box<dyn Error>> {
+        self.functions.get(name).copied().ok_or_else(|| format!("Function {} not found", name).into())
+    }
+
+    pub fn public(&self, mangled_name: &str) -> Result<SymbolLocation, Box<dyn Error>> {
+        self.publics.get(mangled_name).copied().ok_or_else(|| format!("Symbol {} not found", mangled_name).into())
+    }
+
+    pub fn vtable(&self, type_name: &str) -> Result<SymbolLocation, Box<dyn Error>> {
+        let mangled = type_name.rsplit("::").fold(String::from("??_7"), |mangled, component| mangled + component + "@");
+        self.public(&format!("{}@6B@", mangled))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use super::*;
+
+    type Entry = HashMap<String, String>;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
+
+    // Reads the records of a stream in the YAML lctwitch.pdb was generated from with llvm-pdbutil yaml2pdb.
+    // Each record is its own fields followed by the entries of its nested list, e.g. the members of a field list.
+    fn records(stream: &str, indent: usize) -> Vec<(String, Vec<Entry>)> {
+        let yaml = std::fs::read_to_string(fixture("lctwitch.yaml")).unwrap();
+        let record_prefix = format!("{}- Kind: ", " ".repeat(indent));
+        let entry_prefix = format!("{}- Kind: ", " ".repeat(indent + 4));
+
+        let mut records: Vec<(String, Vec<Entry>)> = Vec::new();
+
+        for line in yaml[yaml.find(stream).unwrap()..].lines().skip(1).take_while(|line| line.starts_with(' ')) {
+            if let Some(kind) = line.strip_prefix(&record_prefix) {
+                records.push((kind.to_owned(), vec![Entry::new()]));
+            }
+            else if let (Some(kind), Some((_, entries))) = (line.strip_prefix(&entry_prefix), records.last_mut()) {
+                entries.push(Entry::from([("Kind".to_owned(), kind.to_owned())]));
+            }
+            else if let (Some((key, value)), Some((_, entries))) = (line.trim().split_once(": "), records.last_mut()) {
+                entries.last_mut().unwrap().insert(key.to_owned(), value.trim_matches('\'').to_owned());
+            }
+        }
+
+        records
+    }
+
+    fn number<T: std::str::FromStr>(entry: &Entry, key: &str) -> T where T::Err: std::fmt::Debug {
+        entry[key].parse().unwrap()
+    }
+
+    // Structures with the entries of their field list. Type indices start at 0x1000.
+    fn structures() -> Vec<(Entry, Vec<Entry>)> {
+        let records = records("TpiStream:", 4);
+        let field_list = |index: usize| records[index - 0x1000].1[1..].to_vec();
+
+        records.iter()
+            .filter(|(kind, _)| kind == "LF_STRUCTURE")
+            .map(|(_, entries)| (entries[0].clone(), field_list(number(&entries[0], "FieldList"))))
+            .collect()
+    }
+
+    fn open() -> PdbInfo {
+        PdbInfo::open(&fixture("lctwitch.pdb")).unwrap()
+    }
+
+    #[test]
+    fn resolves_type_sizes_and_members() {
+        let pdb = open();
+
+        for (structure, fields) in structures() {
+            let name = &structure["Name"];
+            assert_eq!(pdb.type_size(name).unwrap(), number::<usize>(&structure, "Size"), "size of {}", name);
+
+            for member in fields.iter().filter(|field| field["Kind"] == "LF_MEMBER") {
+                assert_eq!(pdb.member_offset(name, &member["Name"]).unwrap(), number::<usize>(member, "FieldOffset"), "{}::{}", name, member["Name"]);
+            }
+        }
+
+        assert!(pdb.member_offset("C4ControlScript", "iByClient").is_ok());
+        assert!(pdb.member_offset("C4ControlScript", "Missing").is_err());
+        assert!(pdb.type_size("C4Missing").is_err());
+    }
+
+    #[test]
+    fn resolves_vtable_indices() {
+        let pdb = open();
+        let types = records("TpiStream:", 4);
+        let mut checked = 0;
+
+        for (structure, fields) in structures() {
+            let name = &structure["Name"];
+
+            for method in fields.iter().filter(|field| field["Kind"] == "LF_ONEMETHOD") {
+                let offset = number::<i64>(method, "VFTableOffset");
+                let index = pdb.virtual_method_index(name, &method["Name"]).unwrap();
+
+                if offset >= 0 {
+                    assert_eq!(index as i64, offset / 8, "{}::{}", name, method["Name"]);
+                    checked += 1;
+                }
+                else {
+                    // Overrides have no slot of their own and take the one of the base class.
+                    let base = fields.iter().find(|field| field["Kind"] == "LF_BCLASS").unwrap();
+                    let base_name = &types[number::<usize>(base, "Type") - 0x1000].1[0]["Name"];
+                    assert_eq!(index, pdb.virtual_method_index(base_name, &method["Name"]).unwrap(), "{}::{}", name, method["Name"]);
+                }
+            }
+        }
+
+        assert!(checked > 0);
+        assert_eq!(pdb.vtable_entries("C4ControlScript").unwrap(), 7);
+    }
+
+    #[test]
+    fn resolves_functions_and_globals() {
+        let pdb = open();
+
+        for (kind, entries) in records("DbiStream:", 10).into_iter().filter(|(kind, _)| kind != "S_END") {
+            let symbol = &entries[0];
+            let location = SymbolLocation {
+                section: number(symbol, "Segment"),
+                offset: number(symbol, "Offset")
+            };
+
+            match kind.as_str() {
+                "S_GPROC32" => assert_eq!(pdb.function(&symbol["DisplayName"]).unwrap(), location),
+                "S_GDATA32" => assert_eq!(pdb.global(&symbol["DisplayName"]).unwrap().location, location),
+                _ => {}
+            }
+        }
+
+        assert!(pdb.function("C4Missing::Execute").is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,227 @@
+use std::{collections::{BTreeMap, BTreeSet}, error::Error, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::http::{self, ErrorCode};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    #[default]
+    Http,
+    Schedule,
+    Timer,
+    Chaos,
+    // Forwarded by the LCTwitch of a network client.
+    Relay
+}
+
+// Who asked for a script to be run, and through which catalog action, if any.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct Origin {
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub source: Source,
+    #[serde(default)]
+    pub action: Option<String>,
+    // Player controls and chat messages sent through /v1/action/control.
+    #[serde(default)]
+    pub control: bool,
+    // Who the request was made for, e.g. a chat user, as told by a relay. Only recorded, never checked.
+    #[serde(default)]
+    pub user: Option<String>,
+    // The client's own id for the request, e.g. of a channel point redemption.
+    #[serde(default)]
+    pub reference: Option<String>,
+    // The custom reward a redemption passed as reference belongs to, see helix.rs.
+    #[serde(default)]
+    pub reward: Option<String>
+}
+
+impl Origin {
+    pub fn new(source: Source, token: Option<String>) -> Origin {
+        Origin {
+            token,
+            source,
+            action: None,
+            control: false,
+            user: None,
+            reference: None,
+            reward: None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    Offline,
+    Host,
+    Client,
+    Replay,
+    League
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestKind {
+    Script,
+    Action,
+    Control
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Allow,
+    Deny
+}
+
+// The state of the game a request is checked against.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct GameState {
+    pub modes: Vec<GameMode>,
+    pub scripting_in_replays: bool
+}
+
+// Conditions that are left out match everything.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default)]
+    pub tokens: Option<Vec<String>>,
+    #[serde(default)]
+    pub sources: Option<Vec<Source>>,
+    #[serde(default)]
+    pub kinds: Option<Vec<RequestKind>>,
+    #[serde(default)]
+    pub actions: Option<Vec<String>>,
+    #[serde(default)]
+    pub modes: Option<Vec<GameMode>>,
+    pub verdict: Verdict,
+    // Built-in rules keep reporting the error codes of the guards they replace.
+    #[serde(skip)]
+    code: Option<ErrorCode>
+}
+
+impl Rule {
+    fn builtin(name: &str, modes: Vec<GameMode>, code: ErrorCode) -> Rule {
+        Rule {
+            name: format!("builtin:{}", name),
+            tokens: None,
+            sources: None,
+            kinds: None,
+            actions: None,
+            modes: Some(modes),
+            verdict: Verdict::Deny,
+            code: Some(code)
+        }
+    }
+
+    fn matches(&self, origin: &Origin, state: &GameState) -> bool {
+        let kind = if origin.control { RequestKind::Control } else if origin.action.is_some() { RequestKind::Action } else { RequestKind::Script };
+
+        self.tokens.as_ref().map_or(true, |tokens| origin.token.as_ref().map_or(false, |token| tokens.contains(token)))
+            && self.sources.as_ref().map_or(true, |sources| sources.contains(&origin.source))
+            && self.kinds.as_ref().map_or(true, |kinds| kinds.contains(&kind))
+            && self.actions.as_ref().map_or(true, |actions| origin.action.as_ref().map_or(false, |action| actions.contains(action)))
+            && self.modes.as_ref().map_or(true, |modes| modes.iter().any(|mode| state.modes.contains(mode)))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Decision {
+    pub verdict: Verdict,
+    pub rule: String,
+    #[serde(skip)]
+    code: Option<ErrorCode>
+}
+
+impl Decision {
+    // The error to report for a denied request.
+    pub fn error(&self) -> Option<http::Error> {
+        match self.verdict {
+            Verdict::Allow => None,
+            Verdict::Deny => Some(match self.code {
+                Some(code) => code.into(),
+                None => http::Error {
+                    code: ErrorCode::PolicyDenied,
+                    message: format!("Denied by rule {}", self.rule)
+                }
+            })
+        }
+    }
+}
+
+// Rules are checked in order and the first one that matches decides. The built-in rules, which deny what the
+// engine would not allow in the console either, come after the ones from the file, so that those can override them.
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct PolicyFile {
+    // Token name -> secret, sent as "Authorization: Bearer <secret>".
+    #[serde(default)]
+    pub tokens: BTreeMap<String, String>,
+    // Names of the tokens the LCTwitch of network clients use, see relay.rs. Their requests have the source relay.
+    #[serde(default)]
+    pub relay_tokens: BTreeSet<String>,
+    // Names of the tokens that may moderate, e.g. approve what viewers asked for or pause LCTwitch.
+    #[serde(default)]
+    pub moderator_tokens: BTreeSet<String>,
+    // Names of the tokens that may pass channel point redemptions, which LCTwitch then fulfils or refunds.
+    #[serde(default)]
+    pub redemption_tokens: BTreeSet<String>,
+    #[serde(default)]
+    pub rules: Vec<Rule>
+}
+
+impl PolicyFile {
+    pub fn load(path: &Path) -> Result<PolicyFile, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(PolicyFile::default());
+        }
+
+        Ok(serde_json::from_slice::<PolicyFile>(&std::fs::read(path)?)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?)
+    }
+
+    pub fn token_name(&self, secret: &str) -> Option<&str> {
+        self.tokens.iter().find(|(_, token)| token.as_str() == secret).map(|(name, _)| name.as_str())
+    }
+
+    pub fn is_relay_token(&self, name: &str) -> bool {
+        self.relay_tokens.contains(name)
+    }
+
+    pub fn is_moderator_token(&self, name: &str) -> bool {
+        self.moderator_tokens.contains(name)
+    }
+
+    pub fn is_redemption_token(&self, name: &str) -> bool {
+        self.redemption_tokens.contains(name)
+    }
+
+    fn decide<'a>(rules: impl IntoIterator<Item = &'a Rule>, origin: &Origin, state: &GameState) -> Decision {
+        rules.into_iter()
+            .find(|rule| rule.matches(origin, state))
+            .map_or_else(
+                || Decision { verdict: Verdict::Allow, rule: "default".to_owned(), code: None },
+                |rule| Decision { verdict: rule.verdict, rule: rule.name.clone(), code: rule.code }
+            )
+    }
+
+    // Only the rules from the file, e.g. for requests that are relayed to the host.
+    pub fn evaluate_rules(&self, origin: &Origin, state: &GameState) -> Decision {
+        Self::decide(&self.rules, origin, state)
+    }
+
+    pub fn evaluate(&self, origin: &Origin, state: &GameState) -> Decision {
+        let mut builtin = vec![Rule::builtin("not-host", vec![GameMode::Client], ErrorCode::NotHost)];
+        if !state.scripting_in_replays {
+            builtin.push(Rule::builtin("replay", vec![GameMode::Replay], ErrorCode::NoScriptingInReplays));
+        }
+
+        builtin.push(Rule::builtin("league", vec![GameMode::League], ErrorCode::LeagueActive));
+
+        Self::decide(self.rules.iter().chain(builtin.iter()), origin, state)
+    }
+}
\ No newline at end of file
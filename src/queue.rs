@@ -0,0 +1,129 @@
+use std::{collections::VecDeque, sync::{Arc, Mutex}, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{LCTwitch, actions, args, events::Event, http, policy::Origin, scheduler::now};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HeldRequest {
+    Script {
+        script: http::Script
+    },
+    Action {
+        action: String,
+        args: Map<String, Value>
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct HeldItem {
+    pub id: u64,
+    // Passed back in events, e.g. the id of the channel point redemption to refund if the item expires.
+    pub reference: Option<String>,
+    pub queued_at: u64,
+    pub expires_at: u64,
+    pub origin: Origin,
+    pub request: HeldRequest
+}
+
+// Holds requests that arrive while no scenario is running until one is, or until they expire.
+pub struct HoldingQueue {
+    items: Mutex<VecDeque<HeldItem>>,
+    next_id: Mutex<u64>
+}
+
+impl HoldingQueue {
+    pub fn new() -> HoldingQueue {
+        HoldingQueue {
+            items: Mutex::new(VecDeque::new()),
+            next_id: Mutex::new(0)
+        }
+    }
+
+    pub fn hold(&self, request: HeldRequest, origin: Origin, hold_seconds: u64, reference: Option<String>) -> HeldItem {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+
+        let now = now();
+        let item = HeldItem {
+            id,
+            reference,
+            queued_at: now,
+            expires_at: now + hold_seconds * 1000,
+            origin,
+            request
+        };
+
+        self.items.lock().unwrap().push_back(item.clone());
+        item
+    }
+
+    pub fn items(&self) -> Vec<HeldItem> {
+        self.items.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn cancel(&self, id: u64) -> Option<HeldItem> {
+        let mut items = self.items.lock().unwrap();
+        let index = items.iter().position(|item| item.id == id)?;
+        items.remove(index)
+    }
+
+    fn take_expired(&self) -> Vec<HeldItem> {
+        let now = now();
+        let mut items = self.items.lock().unwrap();
+
+        let (expired, pending) = std::mem::take(&mut *items).into_iter().partition::<Vec<_>, _>(|item| item.expires_at <= now);
+        *items = pending.into();
+        expired
+    }
+
+    pub fn take_all(&self) -> Vec<HeldItem> {
+        self.items.lock().unwrap().drain(..).collect()
+    }
+}
+
+async fn execute(instance: Arc<LCTwitch>, item: &HeldItem) -> Result<String, http::Error> {
+    match &item.request {
+        HeldRequest::Script { script } => {
+            let script_text = args::bind(&script.script, &script.args)?;
+            instance.script()?.run_script(&instance, &script_text, &script.options, &item.origin).await.map_err(http::Error::from)
+        },
+        HeldRequest::Action { action, args } => actions::run(instance, action, args, item.origin.clone()).await
+    }
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let mut interval = tokio::time::interval(Duration::from_millis(250));
+
+    loop {
+        interval.tick().await;
+
+        for item in instance.holding_queue().take_expired() {
+            instance.helix().resolve(&item.origin, false);
+            instance.events().publish(Event::HeldExpired {
+                id: item.id,
+                reference: item.reference
+            });
+        }
+
+        if !instance.script().map_or(false, |script| script.is_running()) {
+            continue;
+        }
+
+        // Delivered one after another so that they reach the game in the order they arrived.
+        for item in instance.holding_queue().take_all() {
+            let result = execute(instance.clone(), &item).await;
+
+            instance.events().publish(Event::HeldResult {
+                id: item.id,
+                reference: item.reference,
+                result: result.into()
+            });
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Value;
+use warp::{hyper::StatusCode, reply::{self, Reply, Response}};
+
+use crate::{LCTwitch, config::Config, events::Event, http::{self, ErrorCode}, policy::{GameMode, Origin}};
+
+// Who a request was made for, e.g. a chat user. Only accepted from relays and only recorded in the audit log.
+pub const USER_HEADER: &str = "x-lctwitch-user";
+
+// Forwards requests that only the host may run to the host's LCTwitch. Whether they are run is up to the host's
+// policy, the local one only decides which requests are forwarded at all.
+pub struct Relay {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>
+}
+
+impl Relay {
+    pub fn new(config: &Config) -> Result<Option<Relay>, Box<dyn std::error::Error>> {
+        let url = match config.relay_url() {
+            Some(url) => url.trim_end_matches('/').to_owned(),
+            None => return Ok(None)
+        };
+
+        Ok(Some(Relay {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?,
+            url,
+            token: config.relay_token().map(str::to_owned)
+        }))
+    }
+
+    async fn send(&self, path: &str, body: &impl Serialize, origin: &Origin) -> Result<Response, http::Error> {
+        let mut request = self.client.post(format!("{}/v1/{}", self.url, path))
+            .json(body);
+
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        if let Some(user) = &origin.user {
+            request = request.header(USER_HEADER, user);
+        }
+
+        let relay_error = |e: reqwest::Error| http::Error {
+            code: ErrorCode::RelayFailed,
+            message: format!("{}: {}", ErrorCode::RelayFailed, e)
+        };
+
+        let response = request.send().await.map_err(relay_error)?;
+        let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+
+        // Replies without a body, e.g. 204, are passed on as they are.
+        let bytes = response.bytes().await.map_err(relay_error)?;
+        if bytes.is_empty() {
+            return Ok(status.into_response());
+        }
+
+        let body = serde_json::from_slice::<Value>(&bytes).map_err(|e| http::Error {
+            code: ErrorCode::RelayFailed,
+            message: format!("{}: {}", ErrorCode::RelayFailed, e)
+        })?;
+
+        Ok(reply::with_status(reply::json(&body), status).into_response())
+    }
+}
+
+// Returns the host's reply if the request was forwarded, or None if it should be handled locally.
+pub async fn forward(instance: &LCTwitch, path: &str, body: &impl Serialize, origin: &Origin) -> Result<Option<Response>, http::Error> {
+    let relay = match instance.relay() {
+        Some(relay) => relay,
+        None => return Ok(None)
+    };
+
+    instance.kill_switch().check()?;
+
+    let state = instance.script()?.game_state();
+    if !state.modes.contains(&GameMode::Client) {
+        return Ok(None);
+    }
+
+    // Built-in rules are left to the host, where they are actually meaningful.
+    let decision = instance.policy().evaluate_rules(origin, &state);
+    let error = decision.error();
+
+    instance.events().publish(Event::PolicyDecision {
+        origin: origin.clone(),
+        decision
+    });
+
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    relay.send(path, body, origin).await.map(Some)
+}
\ No newline at end of file
@@ -0,0 +1,146 @@
+use std::{collections::VecDeque, error::Error, ffi::{CStr, c_char}, fs::{File, OpenOptions}, io::{BufRead, BufReader, Write}, path::PathBuf, sync::{Arc, Mutex}, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LCTwitch, events::Event, layout::Layout, policy::{GameMode, Origin}, resolver::Member, script::StdStrBuf};
+
+// Appended to the name of the recording, e.g. Records.c4f/Record001.c4s.lctwitch.jsonl.
+const SIDECAR_SUFFIX: &str = ".lctwitch.jsonl";
+
+// Who sent a script that is part of a recording. The script itself is in the recording, it is only repeated here
+// so that clients do not have to look it up.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Annotation {
+    pub frame: i32,
+    pub origin: Origin,
+    pub script: String
+}
+
+struct Sidecar {
+    path: PathBuf,
+    file: File
+}
+
+// Writes a sidecar file next to every recording LCTwitch sends scripts to, and publishes its annotations when
+// the recording is played back.
+pub struct ReplayAnnotations {
+    record_filename: Member<StdStrBuf>,
+    scenario_filename: *const c_char,
+    sidecar: Mutex<Option<Sidecar>>
+}
+
+unsafe impl Send for ReplayAnnotations {}
+unsafe impl Sync for ReplayAnnotations {}
+
+impl ReplayAnnotations {
+    pub fn new(layout: &Layout) -> Result<ReplayAnnotations, Box<dyn Error>> {
+        Ok(ReplayAnnotations {
+            record_filename: layout.resolve::<StdStrBuf>("Game.Control.pRecord.sFilename")?,
+            scenario_filename: layout.address("Game.ScenarioFilename")? as *const c_char,
+            sidecar: Mutex::new(None)
+        })
+    }
+
+    fn sidecar_path(replay: &str) -> PathBuf {
+        PathBuf::from(format!("{}{}", replay, SIDECAR_SUFFIX))
+    }
+
+    // The record is destroyed when recording stops, so this may only be called in the main thread.
+    pub(crate) unsafe fn annotate(&self, annotation: &Annotation) -> Result<(), Box<dyn Error>> {
+        let mut sidecar = self.sidecar.lock().unwrap();
+
+        let path = match self.record_filename.ptr().map(|filename| (*filename).to_string_lossy()).filter(|filename| !filename.is_empty()) {
+            Some(filename) => Self::sidecar_path(&filename),
+            None => {
+                *sidecar = None;
+                return Ok(());
+            }
+        };
+
+        if sidecar.as_ref().map_or(true, |sidecar| sidecar.path != path) {
+            *sidecar = Some(Sidecar {
+                file: OpenOptions::new().create(true).append(true).open(&path)?,
+                path
+            });
+        }
+
+        if let Some(sidecar) = sidecar.as_mut() {
+            let mut line = serde_json::to_vec(annotation)?;
+            line.push(b'\n');
+            sidecar.file.write_all(&line)?;
+        }
+
+        Ok(())
+    }
+
+    fn scenario_filename(&self) -> String {
+        unsafe { CStr::from_ptr(self.scenario_filename).to_string_lossy().into_owned() }
+    }
+
+    // Replays recorded without LCTwitch simply have no annotations.
+    fn load(replay: &str) -> Result<VecDeque<Annotation>, Box<dyn Error>> {
+        let path = Self::sidecar_path(replay);
+        if !path.exists() {
+            return Ok(VecDeque::new());
+        }
+
+        let mut annotations = Vec::new();
+        for line in BufReader::new(File::open(&path)?).lines() {
+            // A line cut off by a crash is skipped rather than hiding everything after it.
+            if let Ok(annotation) = serde_json::from_str::<Annotation>(&line?) {
+                annotations.push(annotation);
+            }
+        }
+
+        annotations.sort_by_key(|annotation| annotation.frame);
+        Ok(annotations.into())
+    }
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let mut interval = tokio::time::interval(Duration::from_millis(25));
+
+    // The replay being played back and its annotations that are still to be published.
+    let mut playback: Option<(String, VecDeque<Annotation>)> = None;
+
+    loop {
+        interval.tick().await;
+
+        let (script, replay) = match (instance.script(), instance.replay()) {
+            (Ok(script), Ok(replay)) => (script, replay),
+            _ => return
+        };
+
+        if !script.is_running() || !script.game_state().modes.contains(&GameMode::Replay) {
+            playback = None;
+            continue;
+        }
+
+        let filename = replay.scenario_filename();
+        if playback.as_ref().map_or(true, |(playing, _)| *playing != filename) {
+            let annotations = ReplayAnnotations::load(&filename).unwrap_or_else(|e| {
+                instance.log_in_main_thread(format!("LCTwitch: Could not read the annotations of {}: {}", filename, e));
+                VecDeque::new()
+            });
+
+            playback = Some((filename, annotations));
+        }
+
+        let frame = match script.frame_counter() {
+            Some(frame) => frame,
+            None => continue
+        };
+
+        if let Some((_, annotations)) = &mut playback {
+            while annotations.front().map_or(false, |annotation| annotation.frame <= frame) {
+                if let Some(annotation) = annotations.pop_front() {
+                    instance.events().publish(Event::ReplayAnnotation {
+                        frame: annotation.frame,
+                        origin: annotation.origin,
+                        script: annotation.script
+                    });
+                }
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,210 @@
+use std::{error::Error, ffi::c_void, marker::PhantomData};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{image::Image, pdbinfo::{PdbInfo, TypeRef}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Step {
+    Offset(usize),
+    Deref
+}
+
+// A path relative to an instance of some type, e.g. "Status.eState" relative to a C4Network2.
+pub struct Field<T> {
+    steps: Vec<Step>,
+    _marker: PhantomData<*mut T>
+}
+
+impl<T> Field<T> {
+    pub fn from_steps(steps: Vec<Step>) -> Field<T> {
+        Field {
+            steps,
+            _marker: PhantomData
+        }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    // Returns None if a pointer along the path is null.
+    pub unsafe fn ptr(&self, base: *const c_void) -> Option<*mut T> {
+        let mut address = base as *const u8;
+
+        for step in &self.steps {
+            match step {
+                Step::Offset(offset) => address = address.add(*offset),
+                Step::Deref => {
+                    address = *(address as *const *const u8);
+                    if address.is_null() {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        Some(address as *mut T)
+    }
+
+    pub unsafe fn read(&self, base: *const c_void) -> Option<T> where T: Copy {
+        self.ptr(base).map(|ptr| ptr.read())
+    }
+}
+
+// A path starting at a global variable, e.g. "Game.Network.Status.eState".
+pub struct Member<T> {
+    root: *const c_void,
+    field: Field<T>
+}
+
+impl<T> Member<T> {
+    pub fn new(root: *const c_void, field: Field<T>) -> Member<T> {
+        Member {
+            root,
+            field
+        }
+    }
+
+    pub fn root(&self) -> *const c_void {
+        self.root
+    }
+
+    pub fn field(&self) -> &Field<T> {
+        &self.field
+    }
+
+    pub unsafe fn ptr(&self) -> Option<*mut T> {
+        self.field.ptr(self.root)
+    }
+
+    pub unsafe fn read(&self) -> Option<T> where T: Copy {
+        self.field.read(self.root)
+    }
+}
+
+unsafe impl<T> Send for Member<T> {}
+unsafe impl<T> Sync for Member<T> {}
+unsafe impl<T> Send for Field<T> {}
+unsafe impl<T> Sync for Field<T> {}
+
+pub struct Resolver<'a> {
+    pdb: &'a PdbInfo,
+    image: &'a Image
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(pdb: &'a PdbInfo, image: &'a Image) -> Resolver<'a> {
+        Resolver {
+            pdb,
+            image
+        }
+    }
+
+    fn walk(&self, type_ref: TypeRef, path: &str, components: &mut dyn Iterator<Item = &str>) -> Result<(Vec<Step>, TypeRef), Box<dyn Error>> {
+        let mut steps = Vec::new();
+        let mut current = type_ref;
+        let mut offset = 0;
+
+        for component in components {
+            let type_name = match current {
+                TypeRef::Named { name } => name,
+                TypeRef::Pointer { pointee, .. } => match *pointee {
+                    TypeRef::Named { name } => {
+                        if offset != 0 {
+                            steps.push(Step::Offset(offset));
+                            offset = 0;
+                        }
+
+                        steps.push(Step::Deref);
+                        name
+                    },
+                    other => return Err(format!("{}: cannot access {} through a pointer to {}", path, component, other).into())
+                },
+                other => return Err(format!("{}: cannot access {} in {}", path, component, other).into())
+            };
+
+            let member = self.pdb.member(&type_name, component).map_err(|e| format!("{}: {}", path, e))?;
+            offset += member.offset;
+            current = member.type_ref;
+        }
+
+        if offset != 0 {
+            steps.push(Step::Offset(offset));
+        }
+
+        Ok((steps, current))
+    }
+
+    fn field_size(&self, path: &str, type_ref: &TypeRef) -> Result<usize, Box<dyn Error>> {
+        if let TypeRef::Bitfield { .. } = type_ref {
+            return Err(format!("{} is a bitfield", path).into());
+        }
+
+        self.pdb.size_of(type_ref).ok_or_else(|| format!("{} has unknown size ({})", path, type_ref).into())
+    }
+
+    // Returns the RVA of the global the path starts at, the steps from there and the size of the field.
+    pub fn resolve_raw(&self, path: &str) -> Result<(u32, Vec<Step>, usize), Box<dyn Error>> {
+        let mut components = path.split('.');
+        let root_name = components.next().ok_or("Empty member path")?;
+
+        let global = self.pdb.global(root_name)?;
+        let root = self.image.rva(global.location)?;
+
+        let (steps, type_ref) = self.walk(global.type_ref.clone(), path, &mut components)?;
+        Ok((root, steps, self.field_size(path, &type_ref)?))
+    }
+
+    pub fn resolve_field_raw(&self, type_name: &str, path: &str) -> Result<(Vec<Step>, usize), Box<dyn Error>> {
+        let full_path = format!("{}::{}", type_name, path);
+        let (steps, type_ref) = self.walk(TypeRef::Named { name: type_name.to_owned() }, &full_path, &mut path.split('.'))?;
+        Ok((steps, self.field_size(&full_path, &type_ref)?))
+    }
+
+    // Resolves a path like "Game.Network.Status.eState", where the first component is a global variable.
+    pub fn resolve<T>(&self, path: &str) -> Result<Member<T>, Box<dyn Error>> {
+        let (root, steps, size) = self.resolve_raw(path)?;
+        check_size::<T>(path, size)?;
+
+        Ok(Member::new(unsafe { self.image.base().add(root as usize) } as *const c_void, Field::from_steps(steps)))
+    }
+
+    // Resolves a path like "Status.eState" relative to an instance of type_name.
+    pub fn resolve_field<T>(&self, type_name: &str, path: &str) -> Result<Field<T>, Box<dyn Error>> {
+        let (steps, size) = self.resolve_field_raw(type_name, path)?;
+        check_size::<T>(&format!("{}::{}", type_name, path), size)?;
+
+        Ok(Field::from_steps(steps))
+    }
+
+    pub fn address_raw(&self, path: &str) -> Result<(u32, Vec<Step>), Box<dyn Error>> {
+        let mut components = path.split('.');
+        let root_name = components.next().ok_or("Empty member path")?;
+
+        let global = self.pdb.global(root_name)?;
+        let root = self.image.rva(global.location)?;
+
+        let (steps, _) = self.walk(global.type_ref.clone(), path, &mut components)?;
+        Ok((root, steps))
+    }
+
+    // Resolves the address of an object that is embedded in a global, without a size check.
+    pub fn address(&self, path: &str) -> Result<*mut c_void, Box<dyn Error>> {
+        let (root, steps) = self.address_raw(path)?;
+        evaluate_address(self.image, path, root, steps)
+    }
+}
+
+pub fn evaluate_address(image: &Image, path: &str, root: u32, steps: Vec<Step>) -> Result<*mut c_void, Box<dyn Error>> {
+    let root = unsafe { image.base().add(root as usize) } as *const c_void;
+    unsafe { Field::<c_void>::from_steps(steps).ptr(root) }.ok_or_else(|| format!("{} is behind a null pointer", path).into())
+}
+
+pub fn check_size<T>(path: &str, size: usize) -> Result<(), Box<dyn Error>> {
+    if size != std::mem::size_of::<T>() {
+        return Err(format!("{} is {} bytes, expected {} bytes ({})", path, size, std::mem::size_of::<T>(), std::any::type_name::<T>()).into());
+    }
+
+    Ok(())
+}
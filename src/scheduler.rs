@@ -0,0 +1,207 @@
+use std::{error::Error, path::PathBuf, sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::TryRecvError;
+
+use crate::{LCTwitch, args, events::Event, http, policy::Origin};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    // Seconds from now.
+    Delay(u64),
+    Frame(i32),
+    RoundStart
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Due {
+    // Milliseconds since the Unix epoch, so that persisted entries keep their time across restarts.
+    At(u64),
+    Frame(i32),
+    RoundStart
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ScheduledScript {
+    pub id: u64,
+    pub due: Due,
+    pub script: http::Script,
+    #[serde(default)]
+    pub origin: Origin
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ScheduleFile {
+    next_id: u64,
+    entries: Vec<ScheduledScript>
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_millis() as u64)
+}
+
+// Without a schedule file, entries only live as long as the round they were scheduled in,
+// except for those waiting for the next round to start.
+pub struct Scheduler {
+    state: Mutex<ScheduleFile>,
+    file: Option<PathBuf>
+}
+
+impl Scheduler {
+    pub fn new(file: Option<PathBuf>) -> Result<Scheduler, Box<dyn Error>> {
+        let state = match &file {
+            Some(path) if path.exists() => serde_json::from_slice::<ScheduleFile>(&std::fs::read(path)?)
+                .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?,
+            _ => ScheduleFile::default()
+        };
+
+        Ok(Scheduler {
+            state: Mutex::new(state),
+            file
+        })
+    }
+
+    fn save(&self, state: &ScheduleFile) -> Result<(), Box<dyn Error>> {
+        match &self.file {
+            Some(path) => Ok(std::fs::write(path, serde_json::to_vec_pretty(state)?)?),
+            None => Ok(())
+        }
+    }
+
+    pub fn entries(&self) -> Vec<ScheduledScript> {
+        self.state.lock().unwrap().entries.clone()
+    }
+
+    pub fn schedule(&self, script: http::Script, trigger: Trigger, origin: Origin) -> Result<ScheduledScript, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        let entry = ScheduledScript {
+            id: state.next_id + 1,
+            due: match trigger {
+                Trigger::Delay(seconds) => Due::At(now() + seconds * 1000),
+                Trigger::Frame(frame) => Due::Frame(frame),
+                Trigger::RoundStart => Due::RoundStart
+            },
+            script,
+            origin
+        };
+
+        state.next_id = entry.id;
+        state.entries.push(entry.clone());
+
+        if let Err(e) = self.save(&state) {
+            state.entries.pop();
+            return Err(e);
+        }
+
+        Ok(entry)
+    }
+
+    pub fn cancel(&self, id: u64) -> Result<bool, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.entries.iter().position(|entry| entry.id == id) {
+            Some(index) => {
+                let entry = state.entries.remove(index);
+                if let Err(e) = self.save(&state) {
+                    state.entries.insert(index, entry);
+                    return Err(e);
+                }
+
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    pub fn cancel_all(&self) -> Result<usize, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        let entries = std::mem::take(&mut state.entries);
+        if let Err(e) = self.save(&state) {
+            state.entries = entries;
+            return Err(e);
+        }
+
+        Ok(entries.len())
+    }
+
+    // Removes and returns the due entries. They are removed even if saving fails, so that they do not run twice.
+    // Entries that come due while no scenario is running wait for the next one instead of failing.
+    fn take_due(&self, running: bool, frame: Option<i32>, round_started: bool, round_ended: bool) -> (Vec<ScheduledScript>, Result<(), Box<dyn Error>>) {
+        let mut state = self.state.lock().unwrap();
+        let now = now();
+        let persistent = self.file.is_some();
+
+        let (due, pending) = std::mem::take(&mut state.entries).into_iter()
+            .partition::<Vec<_>, _>(|entry| match entry.due {
+                Due::At(at) => running && at <= now,
+                Due::Frame(at) => frame.map_or(false, |frame| frame >= at),
+                Due::RoundStart => round_started
+            });
+
+        state.entries = pending;
+
+        let dropped = if round_ended && !persistent {
+            let count = state.entries.len();
+            state.entries.retain(|entry| entry.due == Due::RoundStart);
+            count != state.entries.len()
+        }
+        else {
+            false
+        };
+
+        let result = if due.is_empty() && !dropped { Ok(()) } else { self.save(&state) };
+        (due, result)
+    }
+}
+
+async fn execute(instance: Arc<LCTwitch>, entry: ScheduledScript) {
+    let result = match instance.script() {
+        Ok(script_engine) => match args::bind(&entry.script.script, &entry.script.args) {
+            Ok(script_text) => script_engine.run_script(&instance, &script_text, &entry.script.options, &entry.origin).await.map_err(http::Error::from),
+            Err(e) => Err(e)
+        },
+        Err(e) => Err(e)
+    };
+
+    instance.events().publish(Event::ScheduledScript {
+        id: entry.id,
+        result: result.into()
+    });
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let mut interval = tokio::time::interval(Duration::from_millis(25));
+    let mut events = instance.events().subscribe();
+
+    loop {
+        interval.tick().await;
+
+        let (mut round_started, mut round_ended) = (false, false);
+        loop {
+            match events.try_recv() {
+                Ok(Event::RoundStarted) => round_started = true,
+                Ok(Event::RoundEnded { .. }) => round_ended = true,
+                Ok(_) | Err(TryRecvError::Lagged(_)) => {},
+                Err(_) => break
+            }
+        }
+
+        let (running, frame) = match instance.script() {
+            Ok(script) => (script.is_running(), script.frame_counter()),
+            Err(_) => (false, None)
+        };
+
+        let (due, result) = instance.scheduler().take_due(running, frame.filter(|_| running), round_started, round_ended);
+        if let Err(e) = result {
+            instance.log_in_main_thread(format!("LCTwitch: Could not save the schedule: {}", e));
+        }
+
+        for entry in due {
+            tokio::spawn(execute(instance.clone(), entry));
+        }
+    }
+}
\ No newline at end of file
@@ -1,32 +1,62 @@
-use std::{ffi::{CString, c_char, c_void, CStr}, error::Error, mem::MaybeUninit, cell::RefCell, ops::{Deref, DerefMut}};
+use std::{ffi::{CString, c_char, c_void, CStr}, error::Error, mem::MaybeUninit, ops::{Deref, DerefMut}, time::Instant};
 
 use byte_strings::c_str;
 use cpp::*;
 
-use crate::{LCTwitch, detour::{self, Module}, dbghelp::{self, Members}, http::{ErrorCode}};
-use windows::{core::PCSTR, Win32::System::{LibraryLoader::GetModuleHandleA}};
-use windows::Win32::System::{Diagnostics::Debug::*, Threading::GetCurrentProcess};
+use crate::{LCTwitch, audit::AuditEntry, control::{CID_SCRIPT, ControlQueue}, events::Event, http::{self, ErrorCode, ScriptOptions, ScriptTarget}, layout::Layout, policy::{GameMode, GameState, Origin}, replay::Annotation, resolver::{Field, Member}, scheduler, signature::Symbols};
 
 type C4AulScriptEngine = c_void;
-type C4Config = c_void;
-type C4Game = c_void;
-type C4GameControl = c_void;
 type C4ControlScript = c_void;
+type C4GameObjects = c_void;
+type C4Object = c_void;
+type C4PlayerList = c_void;
+type C4Player = c_void;
 
 extern "C" {
-    fn malloc(size: usize) -> *mut c_void;
     fn free(ptr: *mut c_void);
 }
 
 #[repr(C)]
 #[derive(Clone, Copy, PartialEq)]
 pub enum C4AulScriptStrict {
+    NonStrict = 0,
+    Strict1 = 1,
+    Strict2 = 2,
     Strict3 = 3
 }
 
+impl TryFrom<u32> for C4AulScriptStrict {
+    type Error = ErrorCode;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::NonStrict),
+            1 => Ok(Self::Strict1),
+            2 => Ok(Self::Strict2),
+            3 => Ok(Self::Strict3),
+            _ => Err(ErrorCode::InvalidStrictness)
+        }
+    }
+}
+
 pub enum ScriptError {
     Code(ErrorCode), 
-    Box(Box<dyn Error + Send + Sync>)
+    Box(Box<dyn Error + Send + Sync>),
+    // An error the engine reported while compiling or running the script.
+    Failed(String),
+    // The script was not sent to the engine, e.g. because of a policy rule or the lint rules.
+    Rejected(http::Error)
+}
+
+impl ScriptError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Code(code) => *code,
+            Self::Box(_) => ErrorCode::InternalServerError,
+            Self::Failed(_) => ErrorCode::ScriptExecutionError,
+            Self::Rejected(error) => error.code
+        }
+    }
 }
 
 impl From<ErrorCode> for ScriptError {
@@ -41,17 +71,30 @@ impl<T> From<T> for ScriptError where T: Into<Box<dyn Error + Send + Sync>> {
     }
 }
 
+impl From<ScriptError> for http::Error {
+    fn from(value: ScriptError) -> Self {
+        match value {
+            ScriptError::Code(code) => code.into(),
+            ScriptError::Box(boxed) => boxed.to_string().into(),
+            ScriptError::Failed(message) => http::Error { code: ErrorCode::ScriptExecutionError, message },
+            ScriptError::Rejected(error) => error
+        }
+    }
+}
+
 impl std::fmt::Display for ScriptError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Code(code) => code.fmt(f),
-            Self::Box(boxed) => boxed.fmt(f)
+            Self::Box(boxed) => boxed.fmt(f),
+            Self::Failed(message) => message.fmt(f),
+            Self::Rejected(error) => error.fmt(f)
         }
     }
 }
 
 #[repr(C)]
-struct C4Value {
+pub(crate) struct C4Value {
     data: usize,
     next: *mut C4Value,
     first_ref: *mut C4Value,
@@ -64,12 +107,26 @@ struct C4Value {
 unsafe impl Send for C4Value {}
 
 #[repr(C)]
-struct StdStrBuf {
-    is_ref: bool,
-    data: *mut c_char,
-    size: usize
+pub(crate) struct StdStrBuf {
+    pub(crate) is_ref: bool,
+    pub(crate) data: *mut c_char,
+    pub(crate) size: usize
+}
+
+impl StdStrBuf {
+    pub(crate) unsafe fn to_string_lossy(&self) -> String {
+        if self.data.is_null() {
+            String::new()
+        }
+        else {
+            CStr::from_ptr(self.data).to_string_lossy().into_owned()
+        }
+    }
 }
 
+// The frame the scripts ran in and one result per script, or an error if none of them could be run.
+type ExecuteResult = Result<(Option<i32>, Vec<Result<AutoFree<c_char>, ScriptError>>), ScriptError>;
+
 struct ExecuteInfo {
     control_script_size: usize,
     script_offset: usize,
@@ -78,13 +135,25 @@ struct ExecuteInfo {
     get_data_string: extern "win64" fn(*const C4Value) -> StdStrBuf,
     c4value_destructor: extern "win64" fn(*mut C4Value),
     stdstrbuf_destructor: extern "win64" fn(*mut StdStrBuf),
-    value_reply: Option<tokio::sync::oneshot::Sender<Result<AutoFree<c_char>, ScriptError>>>,
+    target_obj_offset: usize,
+    strict_offset: usize,
+    context: CString,
+    objects: *mut C4GameObjects,
+    safe_object_pointer: extern "win64" fn(*mut C4GameObjects, i32) -> *mut C4Object,
+    frame_counter: Option<*mut i32>,
+    scripts: Vec<CString>,
+    pass_errors: bool,
+    stop_on_error: bool,
+    value_reply: Option<tokio::sync::oneshot::Sender<ExecuteResult>>,
     original_vtable: *const *const c_void,
 }
 
 const VTABLE_ENTRIES: usize = 7;
 const VTABLE_EXECUTE: usize = 3;
 
+// C4ControlScript::SCOPE_Console, i.e. no target object.
+const SCOPE_CONSOLE: i32 = -2;
+
 
 cpp!{{
     #pragma pointers_to_members(full_generality, single_inheritance)
@@ -138,130 +207,133 @@ impl<T> DerefMut for AutoFree<T> {
 unsafe impl<T> Send for AutoFree<T> {}
 
 pub struct Script {
-    is_running: *const bool,
-    game_control: *mut C4GameControl,
-    constructor: extern "win64" fn(*mut C4ControlScript),
-    buf_copy: extern "win64" fn(*mut c_void),
-    do_input: extern "win64" fn(*mut C4GameControl, i32, *mut C4ControlScript, i32),
+    is_running: Member<bool>,
+    frame_counter: Member<i32>,
+    control: ControlQueue,
     original_vtable: *const *const c_void,
     modified_vtable_with_locator: [*const c_void; VTABLE_ENTRIES + 2],
-    target_obj_offset: usize,
+
+    players: *mut C4PlayerList,
+    get_player: extern "win64" fn(*mut C4PlayerList, i32) -> *mut C4Player,
+    player_cursor: Field<*mut C4Object>,
+    object_number: Field<i32>,
     
-    control_mode: *const i32,
-    allow_scripting_in_replays: *const bool,
+    control_mode: Member<i32>,
+    control_record: Member<*mut c_void>,
+    allow_scripting_in_replays: Member<bool>,
 
-    league_address: *const StdStrBuf,
+    league_address: Member<StdStrBuf>,
 
-    is_host: *const bool,
-    network_enabled: *const bool,
+    is_host: Member<bool>,
+    network_state: Member<i32>,
     
     execute_info: ExecuteInfo
 }
 
 impl Script {
-    pub fn new(clonk_module: &Module) -> Result<Script, Box<dyn Error>> {
-        let clonk_base_address = unsafe { GetModuleHandleA(PCSTR::null())? }.0 as u64;
-
-        let find_member_offset = |members: &mut Members, name: &'static str| members.find(|info| info.name() == name).ok_or(name).map(|info| info.offset());
-
-        let symbol_info = RefCell::new(SYMBOL_INFO {
-            SizeOfStruct: std::mem::size_of::<SYMBOL_INFO>() as u32,
-            ..Default::default()
-        });
-
-        let member = |base: *const c_void, name: &'static str| -> Result<*const c_void, Box<dyn Error>> {
-            unsafe {
-                Ok(base.add(find_member_offset(&mut Members::new(&*symbol_info.borrow_mut())?, name)?))
-            }
+    // Checks the layouts the code below hard-codes against the ones of the running build.
+    fn check_compatibility(layout: &Layout) -> Result<(), Box<dyn Error>> {
+        let mut mismatches = Vec::new();
+        let mut check = |what: &str, result: Result<bool, Box<dyn Error>>| match result {
+            Ok(true) => {},
+            Ok(false) => mismatches.push(what.to_owned()),
+            Err(err) => mismatches.push(format!("{} ({})", what, err))
         };
 
-        let control_script_size = MaybeUninit::<u64>::uninit();
-
-        unsafe {
-            dbghelp::check_result(SymGetTypeFromName(GetCurrentProcess(), clonk_base_address, PCSTR::from_raw(c_str!("C4ControlScript").as_ptr() as *mut u8), symbol_info.as_ptr()))?;
-            dbghelp::check_result(SymGetTypeInfo(GetCurrentProcess(), clonk_base_address, symbol_info.borrow().TypeIndex, TI_GET_LENGTH, control_script_size.as_ptr() as *mut c_void))?;
-        }
-
-        let control_script_size = unsafe { control_script_size.assume_init() } as usize;
-
-        let target_obj_offset = find_member_offset(&mut Members::new(&*symbol_info.borrow_mut())?, "iTargetObj")?;
-
-        let script_offset = find_member_offset(&mut Members::new(&*symbol_info.borrow_mut())?, "Script")?;
-
-        unsafe {
-            dbghelp::check_result(SymGetTypeFromName(GetCurrentProcess(), clonk_base_address, PCSTR::from_raw(c_str!("C4Game").as_ptr() as *mut u8), symbol_info.as_ptr()))?;
-        }
-
-        let game = detour::find_function::<*mut C4Game>(clonk_module, c_str!("Game")).ok_or("Game")?;
-        let is_running = member(game, "IsRunning")? as *const bool;
-        let game_control = member(game, "Control")? as *mut _;
-        let network = member(game, "Network")?;
-        let game_parameters = member(game, "Parameters")? as *mut _;
-        let script_engine = member(game, "ScriptEngine")? as *mut _;
-
-        unsafe {
-            dbghelp::check_result(SymGetTypeFromName(GetCurrentProcess(), clonk_base_address, PCSTR::from_raw(c_str!("C4GameControl").as_ptr() as *mut u8), symbol_info.as_ptr()))?;
-        }
-
-        let control_mode = member(game_control, "eMode")? as *const i32;
-
-        unsafe {
-            dbghelp::check_result(SymGetTypeFromName(GetCurrentProcess(), clonk_base_address, PCSTR::from_raw(c_str!("C4GameParameters").as_ptr() as *mut u8), symbol_info.as_ptr()))?;
-        }
-
-        let league_address = member(game_parameters, "LeagueAddress")? as *const StdStrBuf;
-
-        let config = detour::find_function::<*mut C4Config>(clonk_module, c_str!("Config")).ok_or("Config")?;
-
-        unsafe {
-            dbghelp::check_result(SymGetTypeFromName(GetCurrentProcess(), clonk_base_address, PCSTR::from_raw(c_str!("C4Config").as_ptr() as *mut u8), symbol_info.as_ptr()))?;
-        }
-
-        let general = member(config, "General")?;
-
-        unsafe {
-            dbghelp::check_result(SymGetTypeFromName(GetCurrentProcess(), clonk_base_address, PCSTR::from_raw(c_str!("C4ConfigGeneral").as_ptr() as *mut u8), symbol_info.as_ptr()))?;
-        }
-
-        let allow_scripting_in_replays = member(general, "AllowScriptingInReplays")? as *const bool;
+        check(&format!("C4ControlScript vtable has {} entries", VTABLE_ENTRIES), layout.vtable_entries("C4ControlScript").map(|entries| entries == VTABLE_ENTRIES));
+        check(&format!("C4ControlScript::Execute is vtable entry {}", VTABLE_EXECUTE), layout.virtual_method_index("C4ControlScript", "Execute").map(|index| index == VTABLE_EXECUTE));
+
+        let buf = MaybeUninit::<StdStrBuf>::uninit();
+        let base = buf.as_ptr();
+        let offset = |field: *const u8| field as usize - base as usize;
+        let (is_ref, data, size) = unsafe {
+            (
+                offset(std::ptr::addr_of!((*base).is_ref) as *const u8),
+                offset(std::ptr::addr_of!((*base).data) as *const u8),
+                offset(std::ptr::addr_of!((*base).size) as *const u8)
+            )
+        };
 
-        unsafe {
-            dbghelp::check_result(SymGetTypeFromName(GetCurrentProcess(), clonk_base_address, PCSTR::from_raw(c_str!("C4Network2").as_ptr() as *mut u8), symbol_info.as_ptr()))?;
+        check("StdStrBuf size", layout.type_size("StdStrBuf").map(|s| s == std::mem::size_of::<StdStrBuf>()));
+        check("StdStrBuf::fRef offset", layout.member_offset("StdStrBuf", "fRef").map(|o| o == is_ref));
+        check("StdStrBuf::pData offset", layout.member_offset("StdStrBuf", "pData").map(|o| o == data));
+        check("StdStrBuf::iSize offset", layout.member_offset("StdStrBuf", "iSize").map(|o| o == size));
+        check("C4Value size", layout.type_size("C4Value").map(|s| s == std::mem::size_of::<C4Value>()));
+        check("C4ControlScript::iTargetObj is an int", layout.resolve_field::<i32>("C4ControlScript", "iTargetObj").map(|_| true));
+        check("C4ControlScript::Strict is an int", layout.resolve_field::<i32>("C4ControlScript", "Strict").map(|_| true));
+        check("C4ControlScript::Script is a StdStrBuf", layout.resolve_field::<StdStrBuf>("C4ControlScript", "Script").map(|_| true));
+
+        if mismatches.is_empty() {
+            Ok(())
         }
-
-        let is_host = member(network, "fHost")? as *const bool;
-        let status = member(network, "Status")?;
-
-        unsafe {
-            dbghelp::check_result(SymGetTypeFromName(GetCurrentProcess(), clonk_base_address, PCSTR::from_raw(c_str!("C4Network2Status").as_ptr() as *mut u8), symbol_info.as_ptr()))?;
+        else {
+            Err(format!("Unsupported game build: {}", mismatches.join(", ")).into())
         }
+    }
 
-        let network_enabled = member(status, "eState")? as *const bool;
-
+    pub fn new(symbols: &Symbols, layout: &Layout) -> Result<Script, Box<dyn Error>> {
+        Self::check_compatibility(layout)?;
+
+        let control_script_size = layout.type_size("C4ControlScript")?;
+        let target_obj_offset = layout.member_offset("C4ControlScript", "iTargetObj")?;
+        let strict_offset = layout.member_offset("C4ControlScript", "Strict")?;
+        let script_offset = layout.member_offset("C4ControlScript", "Script")?;
+
+        let is_running = layout.resolve::<bool>("Game.IsRunning")?;
+        let script_engine = layout.address("Game.ScriptEngine")?;
+        let control_mode = layout.resolve::<i32>("Game.Control.eMode")?;
+        let control_record = layout.resolve::<*mut c_void>("Game.Control.pRecord")?;
+        let league_address = layout.resolve::<StdStrBuf>("Game.Parameters.LeagueAddress")?;
+        let allow_scripting_in_replays = layout.resolve::<bool>("Config.General.AllowScriptingInReplays")?;
+        let is_host = layout.resolve::<bool>("Game.Network.fHost")?;
+        let network_state = layout.resolve::<i32>("Game.Network.Status.eState")?;
+        let frame_counter = layout.resolve::<i32>("Game.FrameCounter")?;
+        // Game is a global, so the address stays the same and the Execute callback can read it on its own.
+        let frame_counter_ptr = unsafe { frame_counter.ptr() };
+        let objects = layout.address("Game.Objects")?;
+        let players = layout.address("Game.Players")?;
+        let player_cursor = layout.resolve_field::<*mut C4Object>("C4Player", "Cursor")?;
+        let object_number = layout.resolve_field::<i32>("C4Object", "Number")?;
+
+        let original_vtable = match layout.vtable("C4ControlScript") {
+            Ok(vtable) => vtable,
+            Err(_) => symbols.function(c_str!("C4ControlScript::`vftable'"))?
+        };
 
         let mut obj = Self {
-            game_control,
+            control: ControlQueue::new(symbols, layout)?,
             is_running,
-            constructor: detour::find_function(clonk_module, c_str!("C4ControlPacket::C4ControlPacket")).ok_or("C4ControlPacket::C4ControlPacket")?,
-            buf_copy: detour::find_function(clonk_module, c_str!("StdStrBuf::Copy")).ok_or("StdStrBuf::Copy")?,
-            do_input: detour::find_function(clonk_module, c_str!("C4GameControl::DoInput")).ok_or("C4GameControl::DoInput")?,
-            original_vtable: detour::find_function(clonk_module, c_str!("C4ControlScript::`vftable'")).ok_or("vftable")?,
+            frame_counter,
+            original_vtable,
             modified_vtable_with_locator: [std::ptr::null(); VTABLE_ENTRIES + 2],
-            target_obj_offset,
+            players,
+            get_player: symbols.function(c_str!("C4PlayerList::Get"))?,
+            player_cursor,
+            object_number,
             control_mode,
+            control_record,
             allow_scripting_in_replays,
             league_address,
             is_host,
-            network_enabled,
+            network_state,
 
             execute_info: ExecuteInfo {
                 control_script_size,
                 script_offset,
                 script_engine,
-                direct_exec: detour::find_function(clonk_module, c_str!("C4AulScript::DirectExec")).ok_or("C4AulScript::DirectExec")?,
-                get_data_string: detour::find_function(clonk_module, c_str!("C4Value::GetDataString")).ok_or("C4Value::GetDataString")?,
-                c4value_destructor: detour::find_function(clonk_module, c_str!("C4Value::~C4Value")).ok_or("C4Value::~C4Value")?,
-                stdstrbuf_destructor: detour::find_function(clonk_module, c_str!("StdStrBuf::~StdStrBuf")).ok_or("StdStrBuf::~StdStrBuf")?,
+                direct_exec: symbols.function(c_str!("C4AulScript::DirectExec"))?,
+                get_data_string: symbols.function(c_str!("C4Value::GetDataString"))?,
+                c4value_destructor: symbols.function(c_str!("C4Value::~C4Value"))?,
+                stdstrbuf_destructor: symbols.function(c_str!("StdStrBuf::~StdStrBuf"))?,
+                target_obj_offset,
+                strict_offset,
+                context: CString::default(),
+                scripts: Vec::new(),
+                pass_errors: false,
+                stop_on_error: false,
+                objects,
+                safe_object_pointer: symbols.function(c_str!("C4GameObjects::SafeObjectPointer"))?,
+                frame_counter: frame_counter_ptr,
                 value_reply: None,
                 original_vtable: std::ptr::null()
             },
@@ -286,49 +358,237 @@ impl Script {
         vtable[1 + VTABLE_ENTRIES] = execute_info as *const c_void;
     }
 
-    pub async fn run_script(&self, instance: &LCTwitch, script: &str) -> Result<String, ScriptError> {
-        unsafe {
-            if !*self.is_running {
-                return Err(ErrorCode::NoScenario.into());
+    pub fn is_running(&self) -> bool {
+        unsafe { self.is_running.read().unwrap_or(false) }
+    }
+
+    pub fn frame_counter(&self) -> Option<i32> {
+        unsafe { self.frame_counter.read() }
+    }
+
+    // Returns the number of the object the script should run in, or None if it does not exist (anymore).
+    unsafe fn resolve_target(&self, target: Option<ScriptTarget>) -> Option<i32> {
+        match target {
+            None => Some(SCOPE_CONSOLE),
+            Some(ScriptTarget::Object(number)) => {
+                if number > 0 && !(self.execute_info.safe_object_pointer)(self.execute_info.objects, number).is_null() {
+                    Some(number)
+                }
+                else {
+                    None
+                }
+            },
+            Some(ScriptTarget::CursorOfPlayer(player)) => {
+                let player = (self.get_player)(self.players, player);
+                if player.is_null() {
+                    return None;
+                }
+
+                self.player_cursor.read(player)
+                    .filter(|cursor| !cursor.is_null())
+                    .and_then(|cursor| self.object_number.read(cursor))
             }
+        }
+    }
+
+    pub fn game_state(&self) -> GameState {
+        let mut modes = Vec::new();
 
-            if *self.network_enabled && !*self.is_host {
-                return Err(ErrorCode::NotHost.into());
+        unsafe {
+            if self.control_mode.read() == Some(3) {
+                modes.push(GameMode::Replay);
+            }
+            else if self.network_state.read().unwrap_or(0) == 0 {
+                modes.push(GameMode::Offline);
+            }
+            else if self.is_host.read().unwrap_or(false) {
+                modes.push(GameMode::Host);
+            }
+            else {
+                modes.push(GameMode::Client);
             }
 
-            if *self.control_mode == 3 && !*self.allow_scripting_in_replays {
-                return Err(ErrorCode::NoScriptingInReplays.into());
+            if self.league_address.ptr().map_or(false, |league_address| (*league_address).size > 0) {
+                modes.push(GameMode::League);
             }
 
-            if (*self.league_address).size > 0 {
-                return Err(ErrorCode::LeagueActive.into());
+            GameState {
+                modes,
+                scripting_in_replays: self.allow_scripting_in_replays.read().unwrap_or(false)
             }
         }
+    }
 
-        let script = CString::new(script)?;
+    // Whether the control packets are also executed elsewhere, i.e. by the other clients of a network game or when
+    // the recording is played back. They only get the script text, not what LCTwitch does with it in this process.
+    pub fn is_shared(&self) -> bool {
+        let network = self.game_state().modes.iter().any(|mode| matches!(mode, GameMode::Host | GameMode::Client));
+        network || unsafe { self.control_record.read() }.map_or(false, |record| !record.is_null())
+    }
 
-        let (tx, rx) = tokio::sync::oneshot::channel::<Result<AutoFree<c_char>, ScriptError>>();
+    // Every script LCTwitch runs passes through here or run_batch, so this is where they are audited.
+    fn audit(&self, instance: &LCTwitch, time: u64, started: Instant, frame: Option<i32>, origin: &Origin, script: &str, result: Result<&String, &ScriptError>) {
+        let entry = AuditEntry {
+            time,
+            // Scripts that never reached the engine are logged with the frame they were refused in.
+            frame: frame.or_else(|| self.frame_counter().filter(|_| self.is_running())),
+            origin: origin.clone(),
+            script: script.to_owned(),
+            result: result.map(String::clone).map_err(|e| http::Error { code: e.code(), message: e.to_string() }).into(),
+            duration: started.elapsed().as_millis() as u64
+        };
 
-        instance.run_in_main_thread(move || {
-            let allocated_size = self.execute_info.control_script_size;
+        if let Err(e) = instance.audit().record(&entry) {
+            let message = format!("LCTwitch: Could not write the audit log: {}", e);
+            instance.run_in_main_thread(move || {
+                let _ = instance.log(&message);
+            });
+        }
+
+        instance.helix().resolve(&entry.origin, result.is_ok());
 
-            let memory = cpp!(unsafe [allocated_size as "std::size_t"] -> *mut c_void as "void *" {
-                return ::operator new(allocated_size, std::nothrow);
+        // Scripts that reached the engine are part of the recording, if the game is recorded.
+        if let (Some(frame), Ok(replay), Ok(_) | Err(ScriptError::Failed(_))) = (frame, instance.replay(), result) {
+            let annotation = Annotation {
+                frame,
+                origin: entry.origin,
+                script: entry.script
+            };
+
+            instance.run_in_main_thread(move || {
+                if let Err(e) = unsafe { replay.annotate(&annotation) } {
+                    let _ = instance.log(&format!("LCTwitch: Could not write the replay annotations: {}", e));
+                }
             });
-    
-            if memory.is_null() {
-                return;
+        }
+    }
+
+    pub async fn run_script(&self, instance: &LCTwitch, script: &str, options: &ScriptOptions, origin: &Origin) -> Result<String, ScriptError> {
+        let (time, started) = (scheduler::now(), Instant::now());
+        let (frame, result) = match self.run_script_unaudited(instance, script, options, origin).await {
+            Ok((frame, result)) => (frame, result),
+            Err(e) => (None, Err(e))
+        };
+
+        self.audit(instance, time, started, frame, origin, script, result.as_ref());
+        result
+    }
+
+    async fn run_script_unaudited(&self, instance: &LCTwitch, script: &str, options: &ScriptOptions, origin: &Origin) -> Result<(Option<i32>, Result<String, ScriptError>), ScriptError> {
+        instance.config().lint_rules().check(script).map_err(ScriptError::Rejected)?;
+        let script = CString::new(script)?;
+
+        // Without passing errors, the engine logs them and the script evaluates to nil like in the console.
+        let (frame, mut results) = self.execute(instance, vec![script.clone()], script, options, origin, false, false).await?;
+        Ok((frame, results.pop().ok_or("Script was not executed")?))
+    }
+
+    // Runs all scripts in the same control packet, and therefore in the same frame. Only this process knows how to
+    // run a list of scripts from one packet, so batches of more than one script are refused if the packet is shared.
+    pub async fn run_batch(&self, instance: &LCTwitch, scripts: &[String], options: &ScriptOptions, origin: &Origin, stop_on_error: bool) -> Result<Vec<Result<String, ScriptError>>, ScriptError> {
+        let (time, started) = (scheduler::now(), Instant::now());
+        let result = self.run_batch_unaudited(instance, scripts, options, origin, stop_on_error).await;
+
+        match result {
+            Ok((frame, results)) => {
+                for (script, result) in scripts.iter().zip(&results) {
+                    self.audit(instance, time, started, frame, origin, script, result.as_ref());
+                }
+
+                Ok(results)
+            },
+            // The batch as a whole was refused.
+            Err(e) => {
+                for script in scripts {
+                    self.audit(instance, time, started, None, origin, script, Err(&e));
+                }
+
+                Err(e)
             }
+        }
+    }
+
+    async fn run_batch_unaudited(&self, instance: &LCTwitch, scripts: &[String], options: &ScriptOptions, origin: &Origin, stop_on_error: bool) -> Result<(Option<i32>, Vec<Result<String, ScriptError>>), ScriptError> {
+        for (index, script) in scripts.iter().enumerate() {
+            instance.config().lint_rules().check(script).map_err(|e| ScriptError::Rejected(http::Error { message: format!("scripts[{}]: {}", index, e.message), ..e }))?;
+        }
+
+        let scripts = scripts.iter()
+            .map(|script| CString::new(script.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // A single script is recorded as is, so that it runs the same way everywhere.
+        let record = match scripts.as_slice() {
+            [script] => script.clone(),
+            _ if self.is_shared() => return Err(ErrorCode::Unsynchronized.into()),
+            _ => CString::new(scripts.iter().map(|script| script.to_string_lossy()).collect::<Vec<_>>().join("\n"))?
+        };
+
+        self.execute(instance, scripts, record, options, origin, true, stop_on_error).await
+    }
+
+    // Checks whether anything may be sent to the game on behalf of origin right now.
+    pub fn authorize(&self, instance: &LCTwitch, origin: &Origin) -> Result<(), ScriptError> {
+        instance.kill_switch().check()?;
+
+        if !self.is_running() {
+            return Err(ErrorCode::NoScenario.into());
+        }
+
+        let decision = instance.policy().evaluate(origin, &self.game_state());
+        let error = decision.error();
+
+        instance.events().publish(Event::PolicyDecision {
+            origin: origin.clone(),
+            decision
+        });
+
+        match error {
+            Some(error) => Err(ScriptError::Rejected(error)),
+            None => Ok(())
+        }
+    }
+
+    async fn execute(&self, instance: &LCTwitch, scripts: Vec<CString>, record: CString, options: &ScriptOptions, origin: &Origin, pass_errors: bool, stop_on_error: bool) -> Result<(Option<i32>, Vec<Result<String, ScriptError>>), ScriptError> {
+        let strictness = C4AulScriptStrict::try_from(options.strictness.unwrap_or_else(|| instance.config().default_strictness()))?;
+        let context = CString::new(options.context.as_deref().unwrap_or_else(|| instance.config().default_context())).map_err(|_| ErrorCode::InvalidContext)?;
+        let target = options.target;
+
+        self.authorize(instance, origin)?;
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<ExecuteResult>();
+
+        instance.run_in_main_thread(move || {
+            // Objects can only be looked up safely in the main thread.
+            let target_obj = match unsafe { self.resolve_target(target) } {
+                Some(target_obj) => target_obj,
+                None => {
+                    let _ = tx.send(Err(ErrorCode::ObjectNotFound.into()));
+                    return;
+                }
+            };
 
             unsafe {
-                memory.write_bytes(0, allocated_size);
-            
-                (self.constructor)(memory);
+                let memory = match self.control.allocate(self.execute_info.control_script_size) {
+                    Some(memory) => memory,
+                    None => return
+                };
+
+                // Filled before the execute info takes the sender, so that a failure can still be reported.
+                if !self.control.set_string(memory.add(self.execute_info.script_offset) as *mut StdStrBuf, &record) {
+                    self.control.free(memory);
+                    let _ = tx.send(Err("Out of memory".into()));
+                    return;
+                }
 
                 let mut modified_vtable_with_locator = Box::new(self.modified_vtable_with_locator.clone());
 
                 let execute_info = Box::new(ExecuteInfo {
                     value_reply: Some(tx),
+                    context,
+                    scripts,
+                    pass_errors,
+                    stop_on_error,
                     original_vtable: self.original_vtable,
                     ..self.execute_info
                 });
@@ -337,46 +597,96 @@ impl Script {
 
                 (memory as *mut *const *const c_void).write(modified_vtable_with_locator.as_ptr().add(1));
 
-                (memory.add(self.target_obj_offset) as *mut i32).write(-2);
-                
-                
-                let script_buf = memory.add(self.execute_info.script_offset);
-                (script_buf as *mut u8).write(0);
+                (memory.add(self.execute_info.target_obj_offset) as *mut i32).write(target_obj);
+                // Recorded so that replays execute the script the same way.
+                (memory.add(self.execute_info.strict_offset) as *mut i32).write(strictness as i32);
 
-                let bytes = script.as_bytes_with_nul();
+                Box::into_raw(modified_vtable_with_locator);
+                self.control.push(CID_SCRIPT, memory);
+            }
+        });
 
-                let bytes_buf = malloc(bytes.len()) as *mut c_char;
-                if bytes_buf.is_null() {
-                    cpp!(unsafe [memory as "void *"] {
-                        ::operator delete(memory);
-                    });
+        let (frame, results) = rx.await??;
 
-                    return;
+        Ok((frame, results.into_iter()
+            .map(|result| result.and_then(|value| {
+                unsafe {
+                    CStr::from_ptr(value.0).to_str()
+                    .map(|s| s.to_owned())
+                    .map_err(|e| e.into())
                 }
+            }))
+            .collect()))
+    }
+}
 
-                bytes_buf.copy_from_nonoverlapping(bytes.as_ptr() as *const _, bytes.len());
+unsafe impl Send for Script {}
+unsafe impl Sync for Script {}
 
-                (script_buf.add(8) as *mut *const c_char).write(bytes_buf);
-                (script_buf.add(16) as *mut usize).write(bytes.len());
-                (self.buf_copy)(script_buf);
+// Converts between C4Values and C4Script literals for code that runs inside the engine, e.g. natives.rs.
+#[derive(Clone, Copy)]
+pub(crate) struct ValueFunctions {
+    script_engine: *mut C4AulScriptEngine,
+    direct_exec: *const c_void,
+    get_data_string: *const c_void,
+    stdstrbuf_destructor: *const c_void
+}
 
-                Box::into_raw(modified_vtable_with_locator);
-                (self.do_input)(self.game_control, 0x80 | 0x08, memory, 4);
-            }
+unsafe impl Send for ValueFunctions {}
+unsafe impl Sync for ValueFunctions {}
+
+impl ValueFunctions {
+    // Returns the value as the console would print it, e.g. "abc" with quotes for strings.
+    pub(crate) unsafe fn data_string(&self, value: *const C4Value) -> String {
+        let get_data_string = self.get_data_string;
+        let stdstrbuf_destructor = self.stdstrbuf_destructor;
+        let mut buf: *mut c_char = std::ptr::null_mut();
+        let buf_ptr = &mut buf as *mut *mut c_char;
+
+        cpp!(unsafe [value as "C4Value *", buf_ptr as "const char **", get_data_string as "GetDataStringFunc", stdstrbuf_destructor as "StdStrBufDestructorFunc"] {
+            StdStrBuf buf{(value->*get_data_string)()};
+            *buf_ptr = buf.pData;
+            buf.fRef = true;
+            buf.pData = nullptr;
+            buf.iSize = 0;
+
+            (buf.*stdstrbuf_destructor)();
         });
 
-        rx.await?.and_then(|value| {
-            unsafe {
-                CStr::from_ptr(value.0).to_str()
-                .map(|s| s.to_owned())
-                .map_err(|e| e.into())
-            }
-        })
+        let buf = AutoFree(buf);
+        if buf.0.is_null() {
+            String::new()
+        }
+        else {
+            CStr::from_ptr(buf.0).to_string_lossy().into_owned()
+        }
+    }
+
+    // Evaluates the literal into result, which the caller then owns. Literals that fail to parse evaluate to nil.
+    pub(crate) unsafe fn evaluate_literal(&self, result: *mut C4Value, literal: &CStr) {
+        let script_engine = self.script_engine;
+        let direct_exec = self.direct_exec;
+        let literal = literal.as_ptr();
+        let context = c_str!("LCTwitch").as_ptr();
+        let strictness = C4AulScriptStrict::Strict3 as i32;
+
+        cpp!(unsafe [script_engine as "C4AulScriptEngine *", direct_exec as "DirectExecFunc", result as "C4Value *", literal as "const char *", context as "const char *", strictness as "std::int32_t"] {
+            // C4Value is opaque here, so this moves the value into result without running any destructor.
+            *result = (script_engine->*direct_exec)(nullptr, literal, context, false, strictness);
+        });
     }
 }
 
-unsafe impl Send for Script {}
-unsafe impl Sync for Script {}
+impl Script {
+    pub(crate) fn value_functions(&self) -> ValueFunctions {
+        ValueFunctions {
+            script_engine: self.execute_info.script_engine,
+            direct_exec: self.execute_info.direct_exec as *const c_void,
+            get_data_string: self.execute_info.get_data_string as *const c_void,
+            stdstrbuf_destructor: self.execute_info.stdstrbuf_destructor as *const c_void
+        }
+    }
+}
 
 pub extern "win64" fn control_script_execute(control: *mut C4ControlScript) {
     let mut execute_info = unsafe {
@@ -389,38 +699,90 @@ pub extern "win64" fn control_script_execute(control: *mut C4ControlScript) {
         execute_info
     };
 
-    let script = unsafe {
-        (&*((control as *const u8).add(execute_info.script_offset) as *const StdStrBuf)).data
+    let value_reply = std::mem::replace(&mut execute_info.value_reply, None).unwrap();
+
+    // The object may have been removed between creating the packet and executing it.
+    let target_obj = unsafe { ((control as *const u8).add(execute_info.target_obj_offset) as *const i32).read() };
+    let object = if target_obj == SCOPE_CONSOLE {
+        std::ptr::null_mut()
+    }
+    else {
+        let object = (execute_info.safe_object_pointer)(execute_info.objects, target_obj);
+        if object.is_null() {
+            let _ = value_reply.send(Err(ErrorCode::ObjectNotFound.into()));
+            return;
+        }
+
+        object
     };
 
     let script_engine = execute_info.script_engine;
     let direct_exec = execute_info.direct_exec as *const c_void;
-    let context = b"LCTwitch\0".as_ptr() as *const i8;
-    let strictness = C4AulScriptStrict::Strict3;
-
-    let buf = {
-        let mut buf = MaybeUninit::<AutoFree<c_char>>::uninit();
-        let buf_ptr = buf.as_mut_ptr();
-        let get_data_string = execute_info.get_data_string as *const c_void;
-        let c4value_destructor = execute_info.c4value_destructor as *const c_void;
-        let stdstrbuf_destructor = execute_info.stdstrbuf_destructor as *const c_void;
-
-        cpp!(unsafe [script_engine as "C4AulScriptEngine *", direct_exec as "DirectExecFunc", context as "const char *", script as "const char *", strictness as "std::int32_t", buf_ptr as "const void **", get_data_string as "GetDataStringFunc", c4value_destructor as "C4ValueDestructorFunc", stdstrbuf_destructor as "StdStrBufDestructorFunc"] {
-            C4Value value{(script_engine->*direct_exec)(nullptr, script, context, false, strictness)};
-            StdStrBuf buf{(value.*get_data_string)()};
+    let context = execute_info.context.as_ptr();
+    let strictness = unsafe { ((control as *const u8).add(execute_info.strict_offset) as *const i32).read() };
+    let pass_errors = execute_info.pass_errors;
+    let get_data_string = execute_info.get_data_string as *const c_void;
+    let c4value_destructor = execute_info.c4value_destructor as *const c_void;
+    let stdstrbuf_destructor = execute_info.stdstrbuf_destructor as *const c_void;
+
+    // Read here rather than once the results arrive, when the game may have moved on to later frames.
+    let frame = execute_info.frame_counter.map(|frame_counter| unsafe { frame_counter.read() });
+
+    let mut results = Vec::with_capacity(execute_info.scripts.len());
+    let mut failed = false;
+
+    for script in &execute_info.scripts {
+        if failed && execute_info.stop_on_error {
+            results.push(Err(ErrorCode::NotExecuted.into()));
+            continue;
+        }
 
-            *buf_ptr = buf.pData;
-            buf.fRef = true;
-            buf.pData = nullptr;
-            buf.iSize = 0;
+        let script = script.as_ptr();
+        let mut buf: *mut c_char = std::ptr::null_mut();
+        let buf_ptr = &mut buf as *mut *mut c_char;
+
+        // With fPassErrors set, DirectExec throws instead of logging the error.
+        let succeeded = cpp!(unsafe [script_engine as "C4AulScriptEngine *", object as "void *", direct_exec as "DirectExecFunc", context as "const char *", script as "const char *", pass_errors as "bool", strictness as "std::int32_t", buf_ptr as "const char **", get_data_string as "GetDataStringFunc", c4value_destructor as "C4ValueDestructorFunc", stdstrbuf_destructor as "StdStrBufDestructorFunc"] -> bool as "bool" {
+            try
+            {
+                C4Value value{(script_engine->*direct_exec)(object, script, context, pass_errors, strictness)};
+                StdStrBuf buf{(value.*get_data_string)()};
+
+                *buf_ptr = buf.pData;
+                buf.fRef = true;
+                buf.pData = nullptr;
+                buf.iSize = 0;
+
+                (buf.*stdstrbuf_destructor)();
+                (value.*c4value_destructor)();
+                return true;
+            }
+            catch (const std::exception &e)
+            {
+                *buf_ptr = _strdup(e.what());
+            }
+            catch (...)
+            {
+                *buf_ptr = _strdup("Unknown script error");
+            }
 
-            (buf.*stdstrbuf_destructor)();
-            (value.*c4value_destructor)();
+            return false;
         });
 
-        unsafe { buf.assume_init() }
-    };
+        let buf = AutoFree(buf);
+        if succeeded {
+            results.push(Ok(buf));
+        }
+        else {
+            failed = true;
+            results.push(Err(if buf.0.is_null() {
+                ScriptError::Failed("Unknown script error".to_owned())
+            }
+            else {
+                ScriptError::Failed(unsafe { CStr::from_ptr(buf.0) }.to_string_lossy().into_owned())
+            }));
+        }
+    }
 
-    let value_reply = std::mem::replace(&mut execute_info.value_reply, None);
-    let _ = value_reply.unwrap().send(Ok(buf));
+    let _ = value_reply.send(Ok((frame, results)));
 }
\ No newline at end of file
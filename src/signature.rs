@@ -0,0 +1,241 @@
+use std::{collections::BTreeMap, error::Error, ffi::{c_void, CStr}, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{detour::{self, Module}, image::Image};
+
+// A byte pattern like "48 8B 0D ?? ?? ?? ?? E8", where ?? matches any byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    bytes: Vec<Option<u8>>
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Pattern, Box<dyn Error>> {
+        let bytes = pattern.split_whitespace()
+            .map(|token| match token {
+                "?" | "??" => Ok(None),
+                _ => u8::from_str_radix(token, 16)
+                    .map(Some)
+                    .map_err(|_| format!("Invalid byte {} in pattern {}", token, pattern).into())
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+        if bytes.iter().all(Option::is_none) {
+            return Err(format!("Pattern {} has no fixed bytes", pattern).into());
+        }
+
+        Ok(Pattern { bytes })
+    }
+
+    pub fn matches_at(&self, data: &[u8], position: usize) -> bool {
+        data.get(position..position + self.bytes.len())
+            .map_or(false, |window| window.iter().zip(&self.bytes).all(|(byte, expected)| expected.map_or(true, |expected| *byte == expected)))
+    }
+
+    // Returns the start of every match in data.
+    pub fn scan(&self, data: &[u8]) -> Vec<usize> {
+        if data.len() < self.bytes.len() {
+            return Vec::new();
+        }
+
+        // Only positions where the first fixed byte matches need to be compared in full.
+        let (anchor, anchor_byte) = self.bytes.iter().enumerate()
+            .find_map(|(index, byte)| byte.map(|byte| (index, byte)))
+            .unwrap();
+
+        (0..=data.len() - self.bytes.len())
+            .filter(|&position| data[position + anchor] == anchor_byte && self.matches_at(data, position))
+            .collect()
+    }
+}
+
+// How the symbol is found relative to the start of the match.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Signature {
+    pub pattern: String,
+    // Offset of the symbol, or of the rel32 displacement pointing to it, from the start of the match.
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub rel32: bool,
+    // Offset of the end of the instruction the displacement is relative to. Defaults to right after
+    // the displacement, which is wrong for instructions with an immediate operand after it.
+    #[serde(default)]
+    pub instruction_end: Option<usize>
+}
+
+impl Signature {
+    // Returns the position of the symbol relative to the start of data. The pattern has to match exactly once.
+    pub fn resolve(&self, data: &[u8]) -> Result<isize, Box<dyn Error>> {
+        let pattern = Pattern::parse(&self.pattern)?;
+
+        match pattern.scan(data).as_slice() {
+            [] => Err(format!("Pattern {} not found", self.pattern).into()),
+            [position] => self.extract(data, *position),
+            matches => Err(format!("Pattern {} is ambiguous ({} matches)", self.pattern, matches.len()).into())
+        }
+    }
+
+    pub fn extract(&self, data: &[u8], position: usize) -> Result<isize, Box<dyn Error>> {
+        let location = position + self.offset;
+
+        if !self.rel32 {
+            return Ok(location as isize);
+        }
+
+        let displacement = data.get(location..location + 4)
+            .ok_or_else(|| format!("Displacement of pattern {} is out of bounds", self.pattern))?;
+
+        let displacement = i32::from_le_bytes(displacement.try_into()?) as isize;
+        let instruction_end = position + self.instruction_end.unwrap_or(self.offset + 4);
+
+        Ok(instruction_end as isize + displacement)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct SignatureFile {
+    #[serde(default)]
+    pub signatures: BTreeMap<String, Signature>
+}
+
+impl SignatureFile {
+    // Signature files are named after the fingerprint of the build they were made for. Builds without one
+    // simply have no signatures.
+    pub fn load(directory: &Path, image: &Image) -> Result<SignatureFile, Box<dyn Error>> {
+        let path = directory.join(format!("{}.json", image.fingerprint()));
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(SignatureFile::default()),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e).into())
+        };
+
+        Ok(serde_json::from_slice(&contents).map_err(|e| format!("Could not parse {}: {}", path.display(), e))?)
+    }
+}
+
+// Looks up functions and objects through debug symbols and falls back to scanning the code sections
+// of the image for builds that ship without them.
+pub struct Symbols<'a> {
+    module: &'a Module,
+    image: &'a Image,
+    signatures: SignatureFile
+}
+
+impl<'a> Symbols<'a> {
+    pub fn new(module: &'a Module, image: &'a Image, signatures: SignatureFile) -> Symbols<'a> {
+        Symbols {
+            module,
+            image,
+            signatures
+        }
+    }
+
+    pub fn scan(&self, name: &str) -> Result<*const c_void, Box<dyn Error>> {
+        let signature = self.signatures.signatures.get(name).ok_or_else(|| format!("No signature for {}", name))?;
+
+        let pattern = Pattern::parse(&signature.pattern)?;
+
+        let mut found = Vec::new();
+        for section in self.image.sections().iter().filter(|section| section.is_code()) {
+            let start = unsafe { self.image.base().add(section.virtual_address as usize) };
+            let data = unsafe { std::slice::from_raw_parts(start, section.virtual_size as usize) };
+
+            for position in pattern.scan(data) {
+                found.push(unsafe { start.offset(signature.extract(data, position)?) } as *const c_void);
+            }
+        }
+
+        match found.as_slice() {
+            [] => Err(format!("Signature for {} not found", name).into()),
+            [address] => Ok(*address),
+            matches => Err(format!("Signature for {} is ambiguous ({} matches)", name, matches.len()).into())
+        }
+    }
+
+    pub fn function<T>(&self, name: &CStr) -> Result<T, Box<dyn Error>> where T: Sized {
+        assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<*const c_void>());
+
+        let address = match unsafe { detour::find_function_raw(self.module, name) } {
+            Some(address) => address,
+            None => {
+                let name = name.to_str()?;
+                self.scan(name).map_err(|e| format!("Failed to find {}: {}", name, e))?
+            }
+        };
+
+        Ok(unsafe { std::mem::transmute_copy(&address) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(pattern: &str) -> Signature {
+        Signature {
+            pattern: pattern.to_owned(),
+            offset: 0,
+            rel32: false,
+            instruction_end: None
+        }
+    }
+
+    #[test]
+    fn parses_wildcards() {
+        assert_eq!(Pattern::parse("48 8b ?? ? E8").unwrap().bytes, [Some(0x48), Some(0x8B), None, None, Some(0xE8)]);
+        assert!(Pattern::parse("48 XY").is_err());
+        assert!(Pattern::parse("480").is_err());
+        assert!(Pattern::parse("?? ?").is_err());
+        assert!(Pattern::parse("").is_err());
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        let data = [0x90, 0x48, 0x8B, 0x0D, 0x11, 0x22, 0x33, 0x44, 0xE8, 0x90];
+        assert_eq!(Pattern::parse("48 8B 0D ?? ?? ?? ?? E8").unwrap().scan(&data), [1]);
+        assert_eq!(Pattern::parse("?? ?? 0D").unwrap().scan(&data), [1]);
+        assert_eq!(signature("8B ?? 11").resolve(&data).unwrap(), 2);
+    }
+
+    #[test]
+    fn reports_missing_patterns() {
+        let data = [0x48, 0x8B, 0x0D, 0x11];
+        assert!(Pattern::parse("48 8B 0E").unwrap().scan(&data).is_empty());
+        assert!(Pattern::parse("48 8B 0D 11 22").unwrap().scan(&data).is_empty());
+        assert!(Pattern::parse("48").unwrap().scan(&[]).is_empty());
+        assert!(signature("48 8B 0E").resolve(&data).unwrap_err().to_string().contains("not found"));
+    }
+
+    #[test]
+    fn reports_ambiguous_patterns() {
+        let data = [0xE8, 0x00, 0xC3, 0xE8, 0x01, 0xC3];
+        assert_eq!(Pattern::parse("E8 ?? C3").unwrap().scan(&data), [0, 3]);
+        assert!(signature("E8 ?? C3").resolve(&data).unwrap_err().to_string().contains("ambiguous"));
+        assert_eq!(signature("E8 01 C3").resolve(&data).unwrap(), 3);
+    }
+
+    #[test]
+    fn matches_at_end_of_buffer() {
+        let data = [0x90, 0x90, 0x48, 0x89, 0xC3];
+        assert_eq!(Pattern::parse("48 ?? C3").unwrap().scan(&data), [2]);
+        assert_eq!(Pattern::parse("C3").unwrap().scan(&data), [4]);
+        assert!(Pattern::parse("89 C3 ??").unwrap().scan(&data).is_empty());
+    }
+
+    #[test]
+    fn follows_rel32_displacements() {
+        // call +0x10, followed by a mov with an imm8 after its displacement.
+        let data = [0x90, 0xE8, 0x10, 0x00, 0x00, 0x00, 0x80, 0x3D, 0xF0, 0xFF, 0xFF, 0xFF, 0x01];
+
+        let call = Signature { offset: 2, rel32: true, ..signature("90 E8") };
+        assert_eq!(call.resolve(&data).unwrap(), 6 + 0x10);
+
+        let compare = Signature { offset: 2, rel32: true, instruction_end: Some(7), ..signature("80 3D ?? ?? ?? ?? 01") };
+        assert_eq!(compare.resolve(&data).unwrap(), 6 + 7 - 0x10);
+
+        let truncated = Signature { offset: 2, rel32: true, ..signature("FF FF 01") };
+        assert!(truncated.resolve(&data).is_err());
+    }
+}
\ No newline at end of file
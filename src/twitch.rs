@@ -0,0 +1,234 @@
+use std::{error::Error, sync::{Arc, Mutex}, time::Duration};
+
+use serde_json::Value;
+use tokio::{io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader}, net::TcpStream, sync::{broadcast::error::RecvError, mpsc::{self, Receiver, Sender}}};
+use tokio_rustls::{TlsConnector, rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName}};
+
+use crate::{LCTwitch, events::Event, killswitch};
+
+const SERVER: &str = "irc.chat.twitch.tv";
+const PORT: u16 = 6697;
+
+// Twitch allows 20 messages per 30 seconds for regular users.
+const MESSAGE_INTERVAL: Duration = Duration::from_millis(1500);
+const MAX_PENDING_MESSAGES: usize = 32;
+
+// Followed by "pause [reason]" or "resume", only for moderators and the broadcaster.
+const COMMAND_PREFIX: &str = "!lctwitch ";
+
+#[derive(Clone, Debug)]
+pub struct ChatMessage {
+    pub user: String,
+    pub message: String,
+    pub moderator: bool
+}
+
+impl ChatMessage {
+    // Parses a line like "@badges=... :user!user@user.tmi.twitch.tv PRIVMSG #channel :message".
+    fn parse(line: &str) -> Option<ChatMessage> {
+        let (tags, line) = match line.strip_prefix('@') {
+            Some(tagged) => tagged.split_once(' ')?,
+            None => ("", line)
+        };
+
+        let (prefix, rest) = line.strip_prefix(':')?.split_once(' ')?;
+        let (command, rest) = rest.split_once(' ')?;
+        if command != "PRIVMSG" {
+            return None;
+        }
+
+        Some(ChatMessage {
+            user: prefix.split('!').next()?.to_owned(),
+            message: rest.split_once(" :")?.1.to_owned(),
+            moderator: tags.split(';').any(|tag| tag == "mod=1" || tag.strip_prefix("badges=").map_or(false, |badges| badges.split(',').any(|badge| badge.starts_with("broadcaster/"))))
+        })
+    }
+}
+
+// The chat connection. Without a channel configured, messages are dropped; without a token, the chat is only read.
+pub struct Twitch {
+    outgoing: Sender<String>,
+    incoming: Mutex<Option<Receiver<String>>>
+}
+
+impl Twitch {
+    pub fn new() -> Twitch {
+        let (outgoing, incoming) = mpsc::channel(MAX_PENDING_MESSAGES);
+
+        Twitch {
+            outgoing,
+            incoming: Mutex::new(Some(incoming))
+        }
+    }
+
+    // Messages beyond what the rate limit lets through are dropped rather than delayed indefinitely.
+    pub fn say(&self, message: &str) {
+        let message = message.replace(['\r', '\n'], " ");
+        if !message.trim().is_empty() {
+            let _ = self.outgoing.try_send(message);
+        }
+    }
+}
+
+fn command(instance: &LCTwitch, message: &ChatMessage) {
+    let (command, argument) = match message.message.strip_prefix(COMMAND_PREFIX) {
+        Some(command) => command.trim().split_once(' ').map_or((command.trim(), None), |(command, argument)| (command, Some(argument.trim().to_owned()))),
+        None => return
+    };
+
+    match command {
+        "pause" => if let Err(e) = killswitch::pause(instance, Some(message.user.clone()), argument, instance.config().pause_refund()) {
+            instance.twitch().say(&format!("Paused, but scheduled scripts could not be cancelled: {}", e));
+        },
+        "resume" => {
+            killswitch::resume(instance, Some(message.user.clone()));
+        },
+        _ => {}
+    }
+}
+
+fn tls_connector() -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+    }));
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    TlsConnector::from(Arc::new(config))
+}
+
+async fn session(instance: &Arc<LCTwitch>, channel: &str, outgoing: &mut Receiver<String>) -> Result<(), Box<dyn Error>> {
+    // The token is sent right after connecting, so the connection is always encrypted.
+    let stream = TcpStream::connect((SERVER, PORT)).await?;
+    let stream = tls_connector().connect(ServerName::try_from(SERVER)?, stream).await?;
+    let (reader, mut writer) = io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+
+    let config = instance.config();
+    let can_send = config.twitch_token().is_some();
+
+    let login = match (config.twitch_token(), config.twitch_user()) {
+        (Some(token), Some(user)) => format!("PASS oauth:{}\r\nNICK {}\r\n", token.trim_start_matches("oauth:"), user.to_lowercase()),
+        // Anonymous users can read, but not send.
+        _ => format!("NICK justinfan{}\r\n", rand::random::<u16>())
+    };
+
+    writer.write_all(format!("{}CAP REQ :twitch.tv/tags twitch.tv/commands\r\nJOIN #{}\r\n", login, channel).as_bytes()).await?;
+
+    instance.kv().set_reserved("twitch.connected", Value::Bool(true));
+
+    let mut next_message = tokio::time::Instant::now();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = match line? {
+                    Some(line) => line,
+                    None => return Err("Connection closed".into())
+                };
+
+                if let Some(server) = line.strip_prefix("PING ") {
+                    writer.write_all(format!("PONG {}\r\n", server).as_bytes()).await?;
+                }
+                else if line.contains(" NOTICE * :Login authentication failed") {
+                    return Err("Login authentication failed".into());
+                }
+                else if let Some(message) = ChatMessage::parse(&line) {
+                    instance.kv().set_reserved("twitch.last_chatter", Value::String(message.user.clone()));
+                    instance.kv().set_reserved("twitch.last_message", Value::String(message.message.clone()));
+
+                    if message.moderator {
+                        command(instance, &message);
+                    }
+
+                    instance.events().publish(Event::ChatMessage {
+                        user: message.user,
+                        message: message.message
+                    });
+                }
+            },
+            _ = tokio::time::sleep_until(next_message) => {
+                if let Ok(message) = outgoing.try_recv() {
+                    if can_send {
+                        writer.write_all(format!("PRIVMSG #{} :{}\r\n", channel, message).as_bytes()).await?;
+                    }
+
+                    next_message = tokio::time::Instant::now() + MESSAGE_INTERVAL;
+                }
+                else {
+                    next_message = tokio::time::Instant::now() + Duration::from_millis(100);
+                }
+            }
+        }
+    }
+}
+
+pub async fn run(instance: Arc<LCTwitch>) {
+    let mut outgoing = match instance.twitch().incoming.lock().unwrap().take() {
+        Some(outgoing) => outgoing,
+        None => return
+    };
+
+    let channel = match instance.config().twitch_channel() {
+        Some(channel) => channel.trim_start_matches('#').to_lowercase(),
+        // Dropping the receiver makes say() discard everything.
+        None => return
+    };
+
+    instance.kv().set_reserved("twitch.channel", Value::String(channel.clone()));
+
+    let mut delay = Duration::from_secs(1);
+
+    loop {
+        if let Err(e) = session(&instance, &channel, &mut outgoing).await {
+            instance.log_in_main_thread(format!("LCTwitch: Twitch chat disconnected: {}", e));
+        }
+
+        instance.kv().set_reserved("twitch.connected", Value::Bool(false));
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(Duration::from_secs(60));
+    }
+}
+
+fn announcement(event: &Event) -> Option<String> {
+    let player = |number: i32, name: &Option<String>| name.clone().unwrap_or_else(|| format!("Player {}", number));
+
+    match event {
+        Event::PlayerJoined { player: number, name } => Some(format!("{} joined the game", player(*number, name))),
+        Event::PlayerLeft { player: number, name } => Some(format!("{} left the game", player(*number, name))),
+        Event::PlayerEliminated { player: number, name } => Some(format!("{} has been eliminated", player(*number, name))),
+        Event::CrewDied { player: number, .. } => Some(format!("Player {} lost a crew member", number)),
+        Event::RoundStarted => Some("The round has started".to_owned()),
+        Event::RoundEnded { game_over: true } => Some("The round is over".to_owned()),
+        Event::RoundEnded { game_over: false } => Some("The round has been aborted".to_owned()),
+        Event::ScenarioLoaded => Some("The scenario has been loaded".to_owned()),
+        Event::ScenarioUnloaded => Some("The scenario has been closed".to_owned()),
+        _ => None
+    }
+}
+
+pub async fn announce(instance: Arc<LCTwitch>) {
+    let announcements = instance.config().chat_announcements();
+    if announcements.is_empty() {
+        return;
+    }
+
+    let mut events = instance.events().subscribe();
+
+    loop {
+        match events.recv().await {
+            Ok(event) => if announcements.contains(&event.name()) {
+                if let Some(message) = announcement(&event) {
+                    instance.twitch().say(&message);
+                }
+            },
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break
+        }
+    }
+}
\ No newline at end of file